@@ -0,0 +1,110 @@
+//! Times parsing a long screenplay on one thread and on several, and measures
+//! the memory the parsed document takes. Run with
+//! `cargo bench`, and `cargo bench --features compact` to compare.
+//!
+//! On a single vCPU Intel Xeon VM with 5 GiB of memory and rustc 1.95, the
+//! 26 MiB screenplay parses in 410 ms (354 ms with `compact`), which is
+//! 3.9 ms for every 256 KiB, while spawning and joining a thread takes
+//! 26 µs. With one core there's nothing to gain from more threads, and
+//! the 2, 4 and all threads runs were within 0.96x to 1.33x of the
+//! sequential one, which is the noise of the machine. It hasn't been run on
+//! more cores, so whether threads make parsing faster is still unknown.
+
+use fountain_rs::Document;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Counts the bytes currently allocated:
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const SCENE: &str = "EXT. RIVER - DAY
+
+Edward wades into the water, holding a _wedding ring_ up to the light.
+
+EDWARD
+(to himself)
+They say you can't catch her. But I've never been much for *what they say*.
+
+The fish circles him, slow and patient.
+
+[[Cut this if the act runs long.]]
+
+CUT TO:
+
+";
+
+fn time(runs: u32, mut parse: impl FnMut() -> Document) -> Duration {
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(parse());
+    }
+    start.elapsed() / runs
+}
+
+fn time_spawn(runs: u32) -> Duration {
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::thread::scope(|scope| {
+            scope.spawn(|| std::hint::black_box(0));
+        });
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let text = SCENE.repeat(100_000);
+    let runs = 10;
+    println!("parsing {} MiB, mean of {} runs", text.len() >> 20, runs);
+
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let doc = Document::from(text.as_str());
+    let markup = ALLOCATED.load(Ordering::Relaxed) - before - text.len();
+    let elements = doc.elements().count();
+    println!(
+        "{} elements take {:.1} MiB, {} bytes each",
+        elements,
+        markup as f64 / (1 << 20) as f64,
+        markup / elements
+    );
+    drop(doc);
+
+    let sequential = time(runs, || Document::from(text.as_str()));
+    println!("{:<15} {:>8.1?}", "sequential:", sequential);
+    let per_mib = sequential.as_secs_f64() * (1 << 20) as f64 / text.len() as f64;
+    println!(
+        "{:<15} {:>8.1?} to parse a chunk of 256 KiB",
+        "",
+        Duration::from_secs_f64(per_mib / 4.0)
+    );
+    // What a chunk has to make up for on a thread of its own:
+    let spawn = time_spawn(1000);
+    println!("{:<15} {:>8.1?}", "thread spawn:", spawn);
+    for (name, threads) in [("2 threads", 2), ("4 threads", 4), ("all threads", 0)] {
+        let parallel = time(runs, || Document::from_parallel(text.as_str(), threads));
+        println!(
+            "{:<15} {:>8.1?} ({:.2}x)",
+            format!("{}:", name),
+            parallel,
+            sequential.as_secs_f64() / parallel.as_secs_f64()
+        );
+    }
+}
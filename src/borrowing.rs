@@ -0,0 +1,84 @@
+use crate::diagnostics::Diagnostic;
+use crate::identity::IdGenerator;
+use crate::markup::Spans;
+use crate::parsing::{parse_str, IntElement};
+use crate::screenplay::Screenplay;
+use crate::{Document, Elements};
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A parsed document that borrows its text instead of owning a copy of it,
+/// for reading many scripts that are already in memory, or memory mapped.
+///
+/// It can't be edited; `into_document()` turns it into a `Document` that
+/// can, by copying the text.
+#[derive(Clone, Debug)]
+pub struct DocumentRef<'a> {
+    text: Cow<'a, str>,
+    markup: Vec<IntElement>,
+    diagnostics: Vec<Diagnostic>,
+    ids: IdGenerator,
+}
+
+impl<'a> DocumentRef<'a> {
+    /// Parses `bytes`, which are only copied if they aren't valid UTF-8. Like
+    /// `Parser`, invalid UTF-8 is replaced with U+FFFD.
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self::parse(String::from_utf8_lossy(bytes))
+    }
+
+    fn parse(text: Cow<'a, str>) -> Self {
+        let (mut markup, diagnostics) = parse_str(&text, 0);
+        let mut ids = IdGenerator::new();
+        ids.assign(&mut markup);
+
+        Self {
+            text,
+            markup,
+            diagnostics,
+            ids,
+        }
+    }
+
+    /// Copies the text into a `Document`, keeping the elements and their ids.
+    pub fn into_document(self) -> Document {
+        Document {
+            text: self.text.into_owned(),
+            markup: self.markup,
+            diagnostics: self.diagnostics,
+            ids: self.ids,
+        }
+    }
+}
+
+impl Screenplay for DocumentRef<'_> {
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn elements(&self) -> Elements<'_> {
+        Elements::over(&self.text, &self.markup, 0, self.markup.len())
+    }
+
+    fn blocks(&self) -> Spans<'_> {
+        Spans::new(&self.text, 0, &self.markup)
+    }
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl<'a> From<&'a str> for DocumentRef<'a> {
+    fn from(text: &'a str) -> Self {
+        Self::parse(Cow::Borrowed(text))
+    }
+}
+
+impl<'a> From<DocumentRef<'a>> for Document {
+    fn from(doc: DocumentRef<'a>) -> Self {
+        doc.into_document()
+    }
+}
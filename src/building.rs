@@ -0,0 +1,154 @@
+use crate::markup::ElmType;
+use crate::writing::{escape, write_paragraphs};
+use crate::Document;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Builds a screenplay element by element, and writes it as Fountain that
+/// parses back to the same element types.
+///
+/// All text is taken literally: emphasis markers and other Fountain syntax
+/// in it are escaped, and lines are forced to their type where the parser
+/// would otherwise see them as something else.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptBuilder {
+    paragraphs: Vec<Vec<(ElmType, String)>>,
+}
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heading(&mut self, text: &str) -> &mut Self {
+        self.paragraph(ElmType::Heading, escape(&single_line(text)))
+    }
+
+    /// Adds action, where every line of `text` becomes its own line of
+    /// action. Blank lines split it into separate paragraphs.
+    pub fn action(&mut self, text: &str) -> &mut Self {
+        let mut new_paragraph = true;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() {
+                new_paragraph = true;
+            } else if new_paragraph {
+                self.paragraph(ElmType::Action, escape(line));
+                new_paragraph = false;
+            } else {
+                self.line(ElmType::Action, escape(line));
+            }
+        }
+        self
+    }
+
+    /// Starts a block of dialogue. Follow it up with `dialogue()` and
+    /// `parenthetical()` calls.
+    pub fn character(&mut self, name: &str) -> &mut Self {
+        self.paragraph(ElmType::Character, escape(&single_line(name)))
+    }
+
+    /// Adds a parenthetical to the current block of dialogue. The
+    /// parentheses are added if `text` doesn't have them yet.
+    pub fn parenthetical(&mut self, text: &str) -> &mut Self {
+        let text = single_line(text);
+        let text = if text.starts_with('(') && text.ends_with(')') {
+            escape(&text)
+        } else {
+            format!("({})", escape(&text))
+        };
+        self.dialogue_line(ElmType::Parenthetical, text)
+    }
+
+    /// Adds lines of dialogue to the current block of dialogue.
+    pub fn dialogue(&mut self, text: &str) -> &mut Self {
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            self.dialogue_line(ElmType::Dialogue, escape(line));
+        }
+        self
+    }
+
+    pub fn transition(&mut self, text: &str) -> &mut Self {
+        self.paragraph(ElmType::Transition, escape(&single_line(text)))
+    }
+
+    /// Adds a section heading, where `depth` is the number of '#' (at least
+    /// one) in front of it.
+    pub fn section(&mut self, depth: usize, text: &str) -> &mut Self {
+        let marker = "#".repeat(depth.max(1));
+        let text = format!("{} {}", marker, escape(&single_line(text)));
+        self.paragraph(ElmType::Section, text)
+    }
+
+    pub fn synopsis(&mut self, text: &str) -> &mut Self {
+        let text = format!("= {}", escape(&single_line(text)));
+        self.paragraph(ElmType::Synopsis, text)
+    }
+
+    pub fn note(&mut self, text: &str) -> &mut Self {
+        let text = format!("[[{}]]", escape(&single_line(text)));
+        self.paragraph(ElmType::Note, text)
+    }
+
+    pub fn centered(&mut self, text: &str) -> &mut Self {
+        let text = format!("> {} <", escape(&single_line(text)));
+        self.paragraph(ElmType::Centered, text)
+    }
+
+    /// Adds lyrics, where every line of `text` becomes its own line of the
+    /// same paragraph.
+    pub fn lyrics(&mut self, text: &str) -> &mut Self {
+        let mut new_paragraph = true;
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let line = format!("~{}", escape(line));
+            if new_paragraph {
+                self.paragraph(ElmType::Lyrics, line);
+                new_paragraph = false;
+            } else {
+                self.line(ElmType::Lyrics, line);
+            }
+        }
+        self
+    }
+
+    pub fn page_break(&mut self) -> &mut Self {
+        self.paragraph(ElmType::PageBreak, "===".to_string())
+    }
+
+    pub fn to_fountain(&self) -> String {
+        write_paragraphs(&self.paragraphs)
+    }
+
+    pub fn build(&self) -> Document {
+        Document::from(self.to_fountain())
+    }
+
+    fn paragraph(&mut self, elm_type: ElmType, text: String) -> &mut Self {
+        if !text.is_empty() {
+            self.paragraphs.push(vec![(elm_type, text)]);
+        }
+        self
+    }
+
+    fn line(&mut self, elm_type: ElmType, text: String) -> &mut Self {
+        match self.paragraphs.last_mut() {
+            Some(paragraph) if !text.is_empty() => paragraph.push((elm_type, text)),
+            _ => return self.paragraph(elm_type, text),
+        }
+        self
+    }
+
+    // Lines of dialogue outside of a dialogue block can only be action:
+    fn dialogue_line(&mut self, elm_type: ElmType, text: String) -> &mut Self {
+        match self.paragraphs.last() {
+            Some(paragraph) if paragraph[0].0 == ElmType::Character => self.line(elm_type, text),
+            _ => self.paragraph(ElmType::Action, text),
+        }
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,59 @@
+use alloc::string::String;
+use core::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    /// Text that was probably meant to be styled or structured differently.
+    Warning,
+    /// Markup that changes how the rest of the document is read.
+    Error,
+}
+
+/// A problem found while parsing, like emphasis that's never closed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+    range: Range<usize>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, range: Range<usize>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            range,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    /// The bytes of the document text the diagnostic is about.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub(crate) fn shift(&mut self, from: usize, to: usize) {
+        self.range = self.range.start + to - from..self.range.end + to - from;
+    }
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{} at {}..{}: {}",
+            severity, self.range.start, self.range.end, self.message
+        )
+    }
+}
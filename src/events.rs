@@ -0,0 +1,126 @@
+use crate::markup::{Element, ElmType, Spans};
+
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+
+/// What an `Event::Start` opens and its `Event::End` closes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tag {
+    /// A scene heading and everything after it, up to the next heading or
+    /// section.
+    Scene,
+    /// A character cue with the parentheticals and lines of dialogue after it.
+    DialogueBlock,
+    /// A line of the given type, or a span of emphasis inside one.
+    Element(ElmType),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Event<'s> {
+    Start(Tag),
+    End(Tag),
+    /// Text with emphasis markers, escaping backslashes and other Fountain
+    /// syntax removed.
+    Text(Cow<'s, str>),
+}
+
+/// Iterator over the events of a document, see `Screenplay::events()`.
+#[derive(Clone, Debug)]
+pub struct Events<'s> {
+    blocks: Spans<'s>,
+    queue: VecDeque<Event<'s>>,
+    grouping: Grouping<'s>,
+}
+
+impl<'s> Events<'s> {
+    pub(crate) fn new(blocks: Spans<'s>) -> Self {
+        Self {
+            blocks,
+            queue: VecDeque::new(),
+            grouping: Grouping::default(),
+        }
+    }
+
+    fn push_block(&mut self, block: Element<'s>) {
+        if !block.elm_type().is_block() {
+            return;
+        }
+        let queue = &mut self.queue;
+        self.grouping
+            .enter(&block, &mut |event| queue.push_back(event));
+
+        block.walk(&mut |event| match (queue.back_mut(), event) {
+            (Some(Event::Text(text)), Event::Text(more)) => text.to_mut().push_str(&more),
+            (_, event) => queue.push_back(event),
+        });
+    }
+}
+
+/// Tracks the scene and the dialogue the blocks of a document are in, as
+/// `Tag::Scene` and `Tag::DialogueBlock` group them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Grouping<'s> {
+    /// The heading of the current scene.
+    pub scene: Option<Element<'s>>,
+    /// The character cue of the current dialogue.
+    pub cue: Option<Element<'s>>,
+}
+
+impl<'s> Grouping<'s> {
+    /// Moves on to the next block, passing the `End` and `Start` events of
+    /// the groups it closes and opens to `visit`.
+    pub fn enter(&mut self, block: &Element<'s>, visit: &mut impl FnMut(Event<'s>)) {
+        let elm_type = block.elm_type();
+        let ends_dialogue = !matches!(elm_type, ElmType::Parenthetical | ElmType::Dialogue);
+        if ends_dialogue && self.cue.take().is_some() {
+            visit(Event::End(Tag::DialogueBlock));
+        }
+        if let ElmType::Heading | ElmType::Section = elm_type {
+            if self.scene.take().is_some() {
+                visit(Event::End(Tag::Scene));
+            }
+        }
+        if elm_type == ElmType::Heading {
+            self.scene = Some(block.clone());
+            visit(Event::Start(Tag::Scene));
+        }
+        if elm_type == ElmType::Character {
+            self.cue = Some(block.clone());
+            visit(Event::Start(Tag::DialogueBlock));
+        }
+    }
+
+    /// Closes the groups still open at the end of the document.
+    pub fn close(&mut self, visit: &mut impl FnMut(Event<'s>)) {
+        if self.cue.take().is_some() {
+            visit(Event::End(Tag::DialogueBlock));
+        }
+        if self.scene.take().is_some() {
+            visit(Event::End(Tag::Scene));
+        }
+    }
+}
+
+impl<'s> Iterator for Events<'s> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(event);
+            }
+            match self.blocks.next() {
+                Some(block) => self.push_block(block),
+                None => {
+                    let queue = &mut self.queue;
+                    self.grouping.close(&mut |event| queue.push_back(event));
+                    if queue.is_empty() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
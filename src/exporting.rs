@@ -0,0 +1,166 @@
+use crate::layout::is_centered_key;
+use crate::markup::{push_run, Element, ElmType, Run, Spans, Style};
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A paragraph of a script for the XML formats of screenwriting apps, which
+/// only have the elements that are printed.
+pub struct ScriptParagraph<'s> {
+    pub elm_type: ElmType,
+    pub runs: Vec<Run<'s>>,
+    /// The number of a scene heading, from the Fountain text like "#12A#",
+    /// or the next one after the highest so far that no heading has.
+    pub scene_number: Option<String>,
+    /// Whether a forced page break comes before the paragraph.
+    pub new_page: bool,
+    /// Whether this is the cue of the second speech of a dual dialogue,
+    /// marked with '^'.
+    pub dual: bool,
+}
+
+/// A paragraph of a title page, `centered` for the title and authors, or on
+/// the left. Empty paragraphs go between entries.
+pub struct TitleParagraph<'s> {
+    pub centered: bool,
+    pub runs: Vec<Run<'s>>,
+}
+
+/// Splits the document with the given `blocks` in its title page and the
+/// paragraphs of the script after it. Lyrics, which most apps don't have,
+/// are set in italics, for each format to write as its closest type, with
+/// the lines of a verse in one paragraph.
+pub fn script_paragraphs(blocks: Spans) -> (Vec<TitleParagraph>, Vec<ScriptParagraph>) {
+    let title_page: Vec<_> = blocks
+        .clone()
+        .take_while(|e| matches!(e.elm_type(), ElmType::TitleKey | ElmType::TitleValue))
+        .collect();
+
+    // The numbers given in the text, which the others skip to stay unique:
+    let numbered: BTreeSet<&str> = blocks.clone().filter_map(|e| e.scene_number()).collect();
+
+    let mut paragraphs: Vec<ScriptParagraph> = Vec::new();
+    let mut scene: usize = 0;
+    let mut new_page = false;
+    // Where the last line of lyrics ends, if it's the last paragraph:
+    let mut lyrics_end = None;
+    for block in blocks.skip(title_page.len()) {
+        let elm_type = block.elm_type();
+        let mut runs = block.runs();
+        let mut scene_number = None;
+        let mut dual = false;
+        match elm_type {
+            ElmType::Heading => {
+                scene_number = Some(match block.scene_number() {
+                    Some(number) => {
+                        // Like 12 for "12A":
+                        let digits = number.find(|c: char| !c.is_ascii_digit());
+                        if let Ok(n) = number[..digits.unwrap_or(number.len())].parse() {
+                            scene = scene.max(n);
+                        }
+                        String::from(number)
+                    }
+                    None => loop {
+                        scene += 1;
+                        let number = format!("{}", scene);
+                        if !numbered.contains(number.as_str()) {
+                            break number;
+                        }
+                    },
+                });
+            }
+            ElmType::Character => {
+                if let Some(last) = runs.last_mut() {
+                    if let Some(name) = last.text.trim_end().strip_suffix('^') {
+                        last.text = String::from(name.trim_end()).into();
+                        dual = true;
+                    }
+                }
+            }
+            ElmType::Lyrics => {
+                for run in &mut runs {
+                    run.style.italic = true;
+                }
+                let end = block.offset() + block.len();
+                // The next line of the same verse:
+                if lyrics_end
+                    .replace(end)
+                    .is_some_and(|prev| prev + 1 == block.offset())
+                {
+                    if let Some(verse) = paragraphs.last_mut() {
+                        let style = Style {
+                            italic: true,
+                            ..Style::default()
+                        };
+                        push_run("\n".into(), style, &mut verse.runs);
+                        for run in runs {
+                            push_run(run.text, run.style, &mut verse.runs);
+                        }
+                        continue;
+                    }
+                }
+            }
+            ElmType::PageBreak => {
+                new_page = true;
+                continue;
+            }
+            ElmType::Action
+            | ElmType::Parenthetical
+            | ElmType::Dialogue
+            | ElmType::Transition
+            | ElmType::Centered => {}
+            // Sections, synopses and notes aren't part of the script:
+            _ => continue,
+        }
+        paragraphs.push(ScriptParagraph {
+            elm_type,
+            runs,
+            scene_number,
+            new_page,
+            dual,
+        });
+        new_page = false;
+        if elm_type != ElmType::Lyrics {
+            lyrics_end = None;
+        }
+    }
+
+    (title_paragraphs(&title_page), paragraphs)
+}
+
+// The title, authors and such are centered, and the contact details after
+// them are on the left, with an empty paragraph between entries:
+fn title_paragraphs<'s>(title_page: &[Element<'s>]) -> Vec<TitleParagraph<'s>> {
+    let (mut centered, mut left) = (Vec::new(), Vec::new());
+    let mut key = "";
+    for element in title_page {
+        key = element.title_key().unwrap_or(key);
+        let centered_key = is_centered_key(key);
+        let paragraphs = if centered_key {
+            &mut centered
+        } else {
+            &mut left
+        };
+        if element.elm_type() == ElmType::TitleKey && !paragraphs.is_empty() {
+            paragraphs.push(TitleParagraph {
+                centered: centered_key,
+                runs: Vec::new(),
+            });
+        }
+        paragraphs.push(TitleParagraph {
+            centered: centered_key,
+            runs: element.runs(),
+        });
+    }
+    if !centered.is_empty() && !left.is_empty() {
+        centered.push(TitleParagraph {
+            centered: true,
+            runs: Vec::new(),
+        });
+    }
+    centered.extend(left);
+
+    centered
+}
@@ -0,0 +1,263 @@
+use crate::exporting::{script_paragraphs, ScriptParagraph};
+use crate::importing::{styled, ImportError, Importer};
+use crate::markup::{ElmType, Run, Spans, Style};
+use crate::xml::{parse_xml, push_escaped, XmlElement};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::iter::Peekable;
+
+/// Writes the document with the given `blocks` as a Final Draft (.fdx) file.
+pub fn write_fdx(blocks: Spans) -> String {
+    let (title_page, paragraphs) = script_paragraphs(blocks);
+
+    let mut fdx = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\" ?>\n");
+    fdx.push_str("<FinalDraft DocumentType=\"Script\" Template=\"No\" Version=\"5\">\n");
+    fdx.push_str("<Content>\n");
+    let mut i = 0;
+    while i < paragraphs.len() {
+        // The '^' marking the second half of a dual dialogue becomes a
+        // <DualDialogue> around both:
+        let end = speech_end(&paragraphs, i);
+        let next = paragraphs.get(end);
+        if paragraphs[i].elm_type == ElmType::Character && next.is_some_and(|p| p.dual) {
+            let dual_end = speech_end(&paragraphs, end);
+            fdx.push_str("<Paragraph>\n<DualDialogue>\n");
+            for paragraph in &paragraphs[i..dual_end] {
+                write_paragraph(&mut fdx, paragraph);
+            }
+            fdx.push_str("</DualDialogue>\n</Paragraph>\n");
+            i = dual_end;
+        } else {
+            for paragraph in &paragraphs[i..end] {
+                write_paragraph(&mut fdx, paragraph);
+            }
+            i = end;
+        }
+    }
+    fdx.push_str("</Content>\n");
+
+    if !title_page.is_empty() {
+        fdx.push_str("<TitlePage>\n<Content>\n");
+        for paragraph in &title_page {
+            let alignment = if paragraph.centered { "Center" } else { "Left" };
+            fdx.push_str(&format!("<Paragraph Alignment=\"{}\">\n", alignment));
+            write_runs(&mut fdx, &paragraph.runs);
+            fdx.push_str("</Paragraph>\n");
+        }
+        fdx.push_str("</Content>\n</TitlePage>\n");
+    }
+    fdx.push_str("</FinalDraft>\n");
+
+    fdx
+}
+
+// Where the speech starting with the cue at `start` ends, or just the
+// paragraph at `start` if it isn't a cue:
+fn speech_end(paragraphs: &[ScriptParagraph], start: usize) -> usize {
+    if paragraphs[start].elm_type != ElmType::Character {
+        return start + 1;
+    }
+    let speech = paragraphs[start + 1..]
+        .iter()
+        .take_while(|p| matches!(p.elm_type, ElmType::Parenthetical | ElmType::Dialogue))
+        .count();
+
+    start + 1 + speech
+}
+
+fn write_paragraph(fdx: &mut String, paragraph: &ScriptParagraph) {
+    let fdx_type = match paragraph.elm_type {
+        ElmType::Heading => "Scene Heading",
+        ElmType::Character => "Character",
+        ElmType::Parenthetical => "Parenthetical",
+        ElmType::Dialogue => "Dialogue",
+        ElmType::Transition => "Transition",
+        // Final Draft has no lyrics, they're set in italics instead:
+        ElmType::Lyrics => "General",
+        _ => "Action",
+    };
+    fdx.push_str(&format!("<Paragraph Type=\"{}\"", fdx_type));
+    if let Some(number) = &paragraph.scene_number {
+        fdx.push_str(" Number=\"");
+        push_escaped(fdx, number);
+        fdx.push('"');
+    }
+    if paragraph.elm_type == ElmType::Centered {
+        fdx.push_str(" Alignment=\"Center\"");
+    }
+    if paragraph.new_page {
+        fdx.push_str(" StartsNewPage=\"Yes\"");
+    }
+    fdx.push_str(">\n");
+    write_runs(fdx, &paragraph.runs);
+    fdx.push_str("</Paragraph>\n");
+}
+
+fn write_runs(fdx: &mut String, runs: &[Run]) {
+    if runs.is_empty() {
+        fdx.push_str("<Text></Text>\n");
+    }
+    for run in runs {
+        let style = run.style();
+        let names: Vec<&str> = [
+            (style.bold, "Bold"),
+            (style.italic, "Italic"),
+            (style.underline, "Underline"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect();
+        if names.is_empty() {
+            fdx.push_str("<Text>");
+        } else {
+            fdx.push_str(&format!("<Text Style=\"{}\">", names.join("+")));
+        }
+        push_escaped(fdx, run.text());
+        fdx.push_str("</Text>\n");
+    }
+}
+
+/// Reads a Final Draft (.fdx) file as Fountain text, see `Importer`.
+pub fn read_fdx(xml: &str) -> Result<String, ImportError> {
+    let root = parse_xml(xml)?;
+    if root.name != "FinalDraft" {
+        return Err(ImportError::UnexpectedRoot(root.name.clone()));
+    }
+
+    let mut importer = Importer::default();
+    if let Some(title_page) = root.child("TitlePage").and_then(|t| t.child("Content")) {
+        let paragraphs = title_page.elements().filter(|e| e.name == "Paragraph");
+        importer.title_page(paragraphs.map(|paragraph| {
+            let centered = paragraph.attribute("Alignment") == Some("Center");
+            (centered, styled_text(paragraph))
+        }));
+    }
+    // Newer versions keep the notes apart from the paragraphs they're about,
+    // with the range of characters they're on in the text of the script.
+    // Those without one go at the end:
+    let mut notes: Vec<(usize, String)> = root
+        .child("ScriptNotes")
+        .into_iter()
+        .flat_map(XmlElement::elements)
+        .filter(|e| e.name == "ScriptNote")
+        .map(|note| {
+            let range = note.attribute("Range").unwrap_or("");
+            let start = range.split(',').next().and_then(|s| s.trim().parse().ok());
+            (start.unwrap_or(usize::MAX), note.text())
+        })
+        .collect();
+    notes.sort_by_key(|&(start, _)| start);
+    let mut notes = NoteAnchors {
+        notes: notes.into_iter().peekable(),
+        position: 0,
+    };
+
+    let content = root
+        .child("Content")
+        .into_iter()
+        .flat_map(XmlElement::elements);
+    for paragraph in content.filter(|e| e.name == "Paragraph") {
+        match paragraph.child("DualDialogue") {
+            Some(dual) => {
+                let mut cues = 0;
+                for paragraph in dual.elements().filter(|e| e.name == "Paragraph") {
+                    if paragraph.attribute("Type") == Some("Character") {
+                        cues += 1;
+                    }
+                    read_paragraph(&mut importer, paragraph, cues == 2, &mut notes);
+                }
+            }
+            None => read_paragraph(&mut importer, paragraph, false, &mut notes),
+        }
+    }
+    for (_, note) in notes.notes {
+        importer.note(&note);
+    }
+
+    Ok(importer.finish())
+}
+
+// The notes kept apart from the paragraphs, by where they start, and the
+// position in the text of the script of the next paragraph, counting a line
+// break after each one:
+struct NoteAnchors<I: Iterator<Item = (usize, String)>> {
+    notes: Peekable<I>,
+    position: usize,
+}
+
+impl<I: Iterator<Item = (usize, String)>> NoteAnchors<I> {
+    // The notes starting in the next paragraph, with `len` characters:
+    fn take(&mut self, len: usize) -> Vec<String> {
+        self.position += len + 1;
+        let mut notes = Vec::new();
+        while let Some((_, note)) = self.notes.next_if(|&(start, _)| start < self.position) {
+            notes.push(note);
+        }
+
+        notes
+    }
+}
+
+fn read_paragraph<I>(
+    importer: &mut Importer,
+    paragraph: &XmlElement,
+    dual: bool,
+    anchors: &mut NoteAnchors<I>,
+) where
+    I: Iterator<Item = (usize, String)>,
+{
+    let elm_type = match paragraph.attribute("Type").unwrap_or("") {
+        "Scene Heading" => ElmType::Heading,
+        "Character" => ElmType::Character,
+        "Parenthetical" => ElmType::Parenthetical,
+        "Dialogue" => ElmType::Dialogue,
+        "Transition" => ElmType::Transition,
+        _ if paragraph.attribute("Alignment") == Some("Center") => ElmType::Centered,
+        _ => ElmType::Action,
+    };
+    if paragraph.attribute("StartsNewPage") == Some("Yes") {
+        importer.page_break();
+    }
+    // Notes in the paragraph, in older versions, go at its end, followed by
+    // those anchored to it:
+    let texts = paragraph.elements().filter(|e| e.name == "Text");
+    let len = texts.map(|text| text.text().chars().count()).sum();
+    let mut notes: Vec<String> = paragraph
+        .elements()
+        .filter(|e| e.name == "ScriptNote")
+        .map(XmlElement::text)
+        .collect();
+    notes.extend(anchors.take(len));
+
+    importer.paragraph(
+        elm_type,
+        &styled_text(paragraph),
+        paragraph.attribute("Number"),
+        dual,
+        &notes,
+    );
+}
+
+// The text of the <Text> elements of a paragraph as Fountain:
+fn styled_text(paragraph: &XmlElement) -> String {
+    let runs = paragraph
+        .elements()
+        .filter(|e| e.name == "Text")
+        .map(|text| {
+            let mut style = Style::default();
+            for name in text.attribute("Style").unwrap_or("").split('+') {
+                match name {
+                    "Bold" => style.bold = true,
+                    "Italic" => style.italic = true,
+                    "Underline" => style.underline = true,
+                    _ => {}
+                }
+            }
+            (text.text(), style)
+        });
+
+    styled(runs)
+}
@@ -0,0 +1,243 @@
+use crate::events::{Event, Events, Tag};
+use crate::markup::{Element, ElmType, Spans};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// How `Screenplay::to_html()` writes a document.
+///
+/// By default it writes a `<div class="screenplay">` to put in a page, with
+/// a class on every line naming its type, like `scene-heading`, `character`
+/// or `dialogue`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Writes a whole HTML page around the screenplay.
+    pub standalone: bool,
+    /// Adds `SCREENPLAY_CSS` to the page, when `standalone` is set.
+    pub stylesheet: bool,
+}
+
+/// A stylesheet laying out the HTML of `Screenplay::to_html()` like a printed
+/// screenplay.
+pub const SCREENPLAY_CSS: &str = r#".screenplay {
+  font-family: "Courier Prime", "Courier New", Courier, monospace;
+  font-size: 12pt;
+  line-height: 1.2;
+  max-width: 6in;
+  margin: 1in auto;
+}
+.screenplay p, .screenplay h1, .screenplay h2, .screenplay h3,
+.screenplay h4, .screenplay h5, .screenplay h6 {
+  font-size: 1em;
+  font-weight: normal;
+  margin: 0 0 1.2em;
+}
+.screenplay .scene-heading { font-weight: bold; margin-top: 2.4em; }
+.screenplay .dialogue-block { margin: 0 1.5in 1.2em 1in; }
+.screenplay .dialogue-block p { margin: 0; }
+.screenplay .character { margin-left: 1.2in; }
+.screenplay .parenthetical { margin-left: 0.6in; margin-right: 0.5in; }
+.screenplay .transition { text-align: right; }
+.screenplay .centered { text-align: center; }
+.screenplay .lyrics { font-style: italic; }
+.screenplay .section, .screenplay .synopsis, .screenplay .note { color: #777; }
+.screenplay .page-break { border: none; break-after: page; }
+.screenplay .title-page { text-align: center; break-after: page; }
+.screenplay .title-page .title { font-weight: bold; margin-top: 3in; }
+.screenplay .title-page .contact, .screenplay .title-page .draft-date {
+  text-align: left;
+}
+"#;
+
+/// Writes the document with the given `blocks` as HTML.
+pub fn write_html(blocks: Spans, options: HtmlOptions) -> String {
+    let title_page: Vec<_> = blocks
+        .clone()
+        .take_while(|e| matches!(e.elm_type(), ElmType::TitleKey | ElmType::TitleValue))
+        .collect();
+
+    let mut html = String::new();
+    if options.standalone {
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let title = title_page.iter().find(|e| e.title_key() == Some("Title"));
+        html.push_str("<title>");
+        if let Some(title) = title {
+            title.walk(&mut |event| {
+                if let Event::Text(text) = event {
+                    push_escaped(&mut html, &text);
+                }
+            });
+        }
+        html.push_str("</title>\n");
+        if options.stylesheet {
+            html.push_str("<style>\n");
+            html.push_str(SCREENPLAY_CSS);
+            html.push_str("</style>\n");
+        }
+        html.push_str("</head>\n<body>\n");
+    }
+
+    html.push_str("<div class=\"screenplay\">\n");
+    if !title_page.is_empty() {
+        write_title_page(&mut html, &title_page);
+    }
+    let mut body = blocks;
+    for _ in 0..title_page.len() {
+        body.next();
+    }
+    write_body(&mut html, body);
+    html.push_str("</div>\n");
+
+    if options.standalone {
+        html.push_str("</body>\n</html>\n");
+    }
+
+    html
+}
+
+// Every key of the title page becomes a <div> with a class named after it,
+// holding a paragraph for each line of its value:
+fn write_title_page(html: &mut String, title_page: &[Element]) {
+    html.push_str("<section class=\"title-page\">\n");
+    let mut in_key = false;
+    for element in title_page {
+        if let Some(key) = element.title_key() {
+            if in_key {
+                html.push_str("</div>\n");
+            }
+            in_key = true;
+            let class = key.to_ascii_lowercase().replace(' ', "-");
+            html.push_str(&format!("<div class=\"{}\">\n", class));
+        }
+        let mut line = String::new();
+        write_inline(&mut line, element);
+        if !line.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", line));
+        }
+    }
+    if in_key {
+        html.push_str("</div>\n");
+    }
+    html.push_str("</section>\n");
+}
+
+// Scenes and dialogue blocks are grouped by `Events`, and every line becomes
+// an element with a class naming its type:
+fn write_body(html: &mut String, blocks: Spans) {
+    // The lines the events are about, for what they don't tell, like the
+    // depth of a section:
+    let mut lines = blocks.clone().filter(|e| e.elm_type().is_block());
+    // How deep in elements the events are, and the tag closing the line, if
+    // its contents are written:
+    let (mut depth, mut close) = (0, None);
+    for event in Events::new(blocks) {
+        match event {
+            Event::Start(Tag::Scene) => html.push_str("<section class=\"scene\">\n"),
+            Event::End(Tag::Scene) => html.push_str("</section>\n"),
+            Event::Start(Tag::DialogueBlock) => html.push_str("<div class=\"dialogue-block\">\n"),
+            Event::End(Tag::DialogueBlock) => html.push_str("</div>\n"),
+            Event::Start(Tag::Element(elm_type)) if depth == 0 => {
+                depth += 1;
+                let line = lines.next();
+                let (tag, class) = match elm_type {
+                    ElmType::PageBreak => {
+                        html.push_str("<hr class=\"page-break\">\n");
+                        close = None;
+                        continue;
+                    }
+                    ElmType::Heading => ("h2", "scene-heading"),
+                    ElmType::Section => {
+                        let text = line.as_ref().map_or("", |line| line.text());
+                        let depth = text.len() - text.trim_start_matches('#').len();
+                        (
+                            ["h1", "h2", "h3", "h4", "h5", "h6"][depth.clamp(1, 6) - 1],
+                            "section",
+                        )
+                    }
+                    _ => ("p", class_name(elm_type)),
+                };
+                html.push_str(&format!("<{} class=\"{}\">", tag, class));
+                close = Some(tag);
+            }
+            Event::End(Tag::Element(_)) if depth == 1 => {
+                depth -= 1;
+                if let Some(tag) = close.take() {
+                    html.push_str(&format!("</{}>\n", tag));
+                }
+            }
+            _ if close.is_none() => {}
+            Event::Start(Tag::Element(elm_type)) => {
+                depth += 1;
+                html.push_str(inline_tag(elm_type, true));
+            }
+            Event::End(Tag::Element(elm_type)) => {
+                depth -= 1;
+                html.push_str(inline_tag(elm_type, false));
+            }
+            Event::Text(text) => push_escaped(html, &text),
+        }
+    }
+}
+
+fn class_name(elm_type: ElmType) -> &'static str {
+    match elm_type {
+        ElmType::Action => "action",
+        ElmType::Character => "character",
+        ElmType::Parenthetical => "parenthetical",
+        ElmType::Dialogue => "dialogue",
+        ElmType::Heading => "scene-heading",
+        ElmType::Transition => "transition",
+        ElmType::Section => "section",
+        ElmType::Synopsis => "synopsis",
+        ElmType::Note => "note",
+        ElmType::PageBreak => "page-break",
+        ElmType::Centered => "centered",
+        ElmType::Lyrics => "lyrics",
+        ElmType::TitleKey | ElmType::TitleValue => "title-page",
+        ElmType::Bold
+        | ElmType::Italic
+        | ElmType::BoldItalic
+        | ElmType::Underline
+        | ElmType::Boneyard => "",
+    }
+}
+
+// Writes the text of a line with its emphasis, leaving out the line's own
+// tags:
+fn write_inline(html: &mut String, element: &Element) {
+    element.walk(&mut |event| match event {
+        Event::Start(Tag::Element(elm_type)) => html.push_str(inline_tag(elm_type, true)),
+        Event::End(Tag::Element(elm_type)) => html.push_str(inline_tag(elm_type, false)),
+        Event::Text(text) => push_escaped(html, &text),
+        Event::Start(_) | Event::End(_) => {}
+    });
+}
+
+// The tag opening or closing emphasis of the given type:
+fn inline_tag(elm_type: ElmType, start: bool) -> &'static str {
+    match (elm_type, start) {
+        (ElmType::Bold, true) => "<strong>",
+        (ElmType::Bold, false) => "</strong>",
+        (ElmType::Italic, true) => "<em>",
+        (ElmType::Italic, false) => "</em>",
+        (ElmType::BoldItalic, true) => "<strong><em>",
+        (ElmType::BoldItalic, false) => "</em></strong>",
+        (ElmType::Underline, true) => "<u>",
+        (ElmType::Underline, false) => "</u>",
+        _ => "",
+    }
+}
+
+fn push_escaped(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(ch),
+        }
+    }
+}
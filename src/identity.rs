@@ -0,0 +1,159 @@
+use crate::markup::ElmType;
+use crate::parsing::IntElement;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "compact")]
+use core::num::NonZeroU32 as IdRepr;
+#[cfg(not(feature = "compact"))]
+use core::num::NonZeroU64 as IdRepr;
+
+/// Identifies an element of a `Document` across edits, see `Element::id()`.
+///
+/// An element keeps its id as long as its type and text stay the same, or
+/// its text is only lightly edited. Ids are unique within a document, and
+/// never reused for another element. With the `compact` feature a document
+/// that goes through more than 2^32 elements runs out of ids, and the
+/// elements parsed after that have none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElementId(IdRepr);
+
+impl ElementId {
+    // Ids are stored in 32 bits with the `compact` feature:
+    #[allow(clippy::useless_conversion)]
+    pub fn get(self) -> u64 {
+        self.0.get().into()
+    }
+}
+
+/// Hands out the ids of a document.
+#[derive(Clone, Debug)]
+pub(crate) struct IdGenerator {
+    // `None` once every id has been handed out:
+    next: Option<IdRepr>,
+}
+
+impl IdGenerator {
+    pub fn new() -> Self {
+        Self {
+            next: Some(IdRepr::MIN),
+        }
+    }
+
+    /// A generator with only the last id left.
+    #[cfg(test)]
+    pub fn running_out() -> Self {
+        Self {
+            next: Some(IdRepr::MAX),
+        }
+    }
+
+    /// Gives a new id to every element of `markup` that doesn't have one,
+    /// while there are ids left.
+    pub fn assign(&mut self, markup: &mut [IntElement]) {
+        for int_elm in markup.iter_mut().filter(|e| e.id.is_none()) {
+            let Some(next) = self.next else {
+                return;
+            };
+            int_elm.id = Some(ElementId(next));
+            self.next = next.checked_add(1);
+        }
+    }
+}
+
+// How many of the unmatched old elements after the last match are tried for
+// a lightly edited new element:
+const EDIT_LOOKAHEAD: usize = 4;
+
+/// Copies the ids of the `old` elements, with their text in `old_text`
+/// starting at `old_offset`, to the `new` elements they most likely became.
+/// New elements that match none keep `id: None`.
+pub(crate) fn carry_ids(
+    old_text: &str,
+    old_offset: usize,
+    old: &[IntElement],
+    new_text: &str,
+    new_offset: usize,
+    new: &mut [IntElement],
+) {
+    let key = |int_elm: &IntElement, text, offset| {
+        (
+            int_elm.elm_type,
+            int_elm.text_in(text, offset).unwrap_or(""),
+        )
+    };
+    let old_keys: Vec<_> = old.iter().map(|e| key(e, old_text, old_offset)).collect();
+    let new_keys: Vec<_> = new.iter().map(|e| key(e, new_text, new_offset)).collect();
+
+    // Most edits leave the elements around them as they were:
+    let prefix = old_keys
+        .iter()
+        .zip(&new_keys)
+        .take_while(|(o, n)| o == n)
+        .count();
+    let suffix = old_keys[prefix..]
+        .iter()
+        .rev()
+        .zip(new_keys[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    for i in 0..prefix {
+        new[i].id = old[i].id;
+    }
+    for i in 1..=suffix {
+        new[new.len() - i].id = old[old.len() - i].id;
+    }
+
+    // Elements that moved, or with changes in between, are matched by their
+    // text, in order for repeated ones like a character's name:
+    let old_middle = prefix..old.len() - suffix;
+    let new_middle = prefix..new.len() - suffix;
+    let mut by_key: BTreeMap<(ElmType, &str), VecDeque<usize>> = BTreeMap::new();
+    for i in old_middle.clone() {
+        by_key.entry(old_keys[i]).or_default().push_back(i);
+    }
+    let mut matched = vec![false; old.len()];
+    for i in new_middle.clone() {
+        if let Some(j) = by_key.get_mut(&new_keys[i]).and_then(VecDeque::pop_front) {
+            new[i].id = old[j].id;
+            matched[j] = true;
+        }
+    }
+
+    // The remaining ones are paired with an old element of the same type
+    // and mostly the same text that comes after the last pair:
+    let unmatched_old: Vec<_> = old_middle.filter(|&j| !matched[j]).collect();
+    let mut next_old = 0;
+    for i in new_middle {
+        if new[i].id.is_some() {
+            continue;
+        }
+        let candidates = unmatched_old.iter().enumerate().skip(next_old);
+        let pair = candidates.take(EDIT_LOOKAHEAD).find(|&(_, &j)| {
+            old_keys[j].0 == new_keys[i].0 && similar(old_keys[j].1, new_keys[i].1)
+        });
+        if let Some((k, &j)) = pair {
+            new[i].id = old[j].id;
+            next_old = k + 1;
+        }
+    }
+}
+
+// Whether the text shared at the start and end of `a` and `b` is at least
+// half of the longer one:
+fn similar(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let shortest = a.len().min(b.len());
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take(shortest - prefix)
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    2 * (prefix + suffix) >= a.len().max(b.len())
+}
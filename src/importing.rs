@@ -0,0 +1,306 @@
+use crate::markup::{scene_number_start, ElmType, Style};
+use crate::writing::{escape, write_paragraphs};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Why a file in another screenplay format couldn't be read, see
+/// `Document::from_fdx()` and `Document::from_osf()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    /// The file isn't well-formed XML, starting at the given byte offset.
+    InvalidXml(usize),
+    /// The XML is fine, but its root element, named here, isn't the one of
+    /// the format.
+    UnexpectedRoot(String),
+    /// The XML nests elements deeper than any screenplay would, starting at
+    /// the given byte offset.
+    TooDeep(usize),
+    /// The file isn't a zip archive, or it's damaged.
+    InvalidZip,
+    /// The zip archive doesn't have the named file the document is in.
+    MissingFile(String),
+    /// The named file in the zip archive is larger than any screenplay
+    /// would be, 64 MiB.
+    TooLarge(String),
+}
+
+impl core::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        match self {
+            ImportError::InvalidXml(offset) => write!(f, "invalid XML at byte {}", offset),
+            ImportError::UnexpectedRoot(name) => write!(f, "unexpected root element <{}>", name),
+            ImportError::TooDeep(offset) => write!(f, "XML nested too deep at byte {}", offset),
+            ImportError::InvalidZip => write!(f, "invalid zip archive"),
+            ImportError::MissingFile(name) => write!(f, "no {} in the zip archive", name),
+            ImportError::TooLarge(name) => write!(f, "{} in the zip archive is too large", name),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ImportError {}
+
+/// Turns the paragraphs of a script in another format into Fountain text
+/// that parses back to the same types, forcing the lines that would
+/// otherwise parse as something else, like `ScriptBuilder` does.
+///
+/// Text given to it is Fountain already, see `styled()`.
+#[derive(Default)]
+pub struct Importer {
+    paragraphs: Vec<Vec<(ElmType, String)>>,
+    new_page: bool,
+}
+
+impl Importer {
+    /// Adds a title page made of `paragraphs`, centered or not. They're
+    /// grouped by the empty ones between them, and named after what's
+    /// usually there: the title, then credit and author in the middle, and
+    /// contact details on the sides.
+    pub fn title_page(&mut self, paragraphs: impl Iterator<Item = (bool, String)>) {
+        let mut groups: Vec<(bool, Vec<String>)> = Vec::new();
+        let mut new_group = true;
+        for (centered, text) in paragraphs {
+            let text = text.trim();
+            if text.is_empty() {
+                new_group = true;
+                continue;
+            }
+            match groups.last_mut() {
+                Some((c, lines)) if !new_group && *c == centered => lines.push(text.to_string()),
+                _ => groups.push((centered, vec![text.to_string()])),
+            }
+            new_group = false;
+        }
+
+        let mut entries: Vec<(&str, Vec<String>)> = Vec::new();
+        for (centered, lines) in groups {
+            let credit = lines.len() == 1 && {
+                let line = lines[0].to_lowercase();
+                line == "by" || line.ends_with(" by")
+            };
+            let key = match entries.last() {
+                _ if !centered => "Contact",
+                None => "Title",
+                _ if credit => "Credit",
+                Some(("Credit", _)) => "Author",
+                _ => "Notes",
+            };
+            match entries.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, values)) => values.extend(lines),
+                None => entries.push((key, lines)),
+            }
+        }
+
+        let mut title_page = Vec::new();
+        for (key, values) in entries {
+            if let [value] = &values[..] {
+                title_page.push((ElmType::TitleKey, format!("{}: {}", key, value)));
+            } else {
+                title_page.push((ElmType::TitleKey, format!("{}:", key)));
+                for value in values {
+                    title_page.push((ElmType::TitleValue, format!("    {}", value)));
+                }
+            }
+        }
+        self.push_paragraph(title_page);
+    }
+
+    /// Puts the next paragraph on a new page.
+    pub fn page_break(&mut self) {
+        self.new_page = true;
+    }
+
+    /// Adds a paragraph of the script. `number` is the scene number of a
+    /// heading, `dual` marks the cue of the second speech of a dual
+    /// dialogue, and the `notes` about the paragraph go at its end.
+    pub fn paragraph(
+        &mut self,
+        elm_type: ElmType,
+        text: &str,
+        number: Option<&str>,
+        dual: bool,
+        notes: &[String],
+    ) {
+        let mut text = String::from(text);
+        for note in notes {
+            text.push_str(&format!(" [[{}]]", note_text(note)));
+        }
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return;
+        }
+        let single_line = lines.join(" ");
+
+        match elm_type {
+            ElmType::Heading => {
+                let mut line = single_line;
+                let number = number.unwrap_or("").trim();
+                let valid = number
+                    .chars()
+                    .all(|ch| ch.is_alphanumeric() || matches!(ch, '.' | '-'));
+                if !number.is_empty() && valid {
+                    line.push_str(&format!(" #{}#", number));
+                } else if let Some(start) = scene_number_start(&line) {
+                    // Text that looks like a scene number isn't one:
+                    line.insert(start, '\\');
+                }
+                self.push_paragraph(vec![(elm_type, line)]);
+            }
+            ElmType::Character => {
+                let mut line = single_line;
+                if dual {
+                    line.push_str(" ^");
+                }
+                self.push_paragraph(vec![(elm_type, line)]);
+            }
+            ElmType::Transition => self.push_paragraph(vec![(elm_type, single_line)]),
+            ElmType::Parenthetical | ElmType::Dialogue => {
+                let line = if elm_type == ElmType::Parenthetical && !single_line.starts_with('(') {
+                    format!("({})", single_line)
+                } else {
+                    single_line
+                };
+                // Dialogue outside of a dialogue block can only be action:
+                match self.paragraphs.last_mut() {
+                    Some(speech) if !self.new_page && speech[0].0 == ElmType::Character => {
+                        speech.push((elm_type, line));
+                    }
+                    _ => self.push_paragraph(vec![(ElmType::Action, line)]),
+                }
+            }
+            ElmType::Centered => {
+                let lines = lines.iter().map(|l| (elm_type, format!("> {} <", l)));
+                self.push_paragraph(lines.collect());
+            }
+            ElmType::Lyrics => {
+                let lines = lines.iter().map(|l| (elm_type, format!("~{}", l)));
+                self.push_paragraph(lines.collect());
+            }
+            _ => {
+                let lines = lines.iter().map(|l| (ElmType::Action, l.to_string()));
+                self.push_paragraph(lines.collect());
+            }
+        }
+    }
+
+    /// Adds a note on a paragraph of its own.
+    pub fn note(&mut self, text: &str) {
+        let note = format!("[[{}]]", note_text(text));
+        self.push_paragraph(vec![(ElmType::Note, note)]);
+    }
+
+    pub fn finish(self) -> String {
+        write_paragraphs(&self.paragraphs)
+    }
+
+    fn push_paragraph(&mut self, paragraph: Vec<(ElmType, String)>) {
+        if paragraph.is_empty() {
+            return;
+        }
+        if self.new_page && !self.paragraphs.is_empty() {
+            self.paragraphs
+                .push(vec![(ElmType::PageBreak, "===".to_string())]);
+        }
+        self.new_page = false;
+        self.paragraphs.push(paragraph);
+    }
+}
+
+/// Writes runs of styled text as Fountain, escaped and with emphasis markers
+/// around the styled ones.
+///
+/// Emphasis only starts and ends at the edges of words in Fountain, so a
+/// word styled in part, like "un**believ**able", is styled whole, with every
+/// style any part of it has. Punctuation at its edges is left as it is.
+pub fn styled(runs: impl Iterator<Item = (String, Style)>) -> String {
+    let mut chars: Vec<(char, Style)> = Vec::new();
+    for (text, style) in runs {
+        chars.extend(text.chars().map(|ch| (ch, style)));
+    }
+    let mut start = 0;
+    for end in 1..=chars.len() {
+        if end < chars.len() && !can_change_style(chars[end - 1], chars[end]) {
+            continue;
+        }
+        let part = &mut chars[start..end];
+        let style = part
+            .iter()
+            .fold(Style::default(), |all, &(_, style)| Style {
+                bold: all.bold || style.bold,
+                italic: all.italic || style.italic,
+                underline: all.underline || style.underline,
+            });
+        for (_, part_style) in part {
+            *part_style = style;
+        }
+        start = end;
+    }
+
+    // Emphasis doesn't go on past the end of a line:
+    let mut merged: Vec<(String, Style)> = Vec::new();
+    for (ch, style) in chars {
+        match merged.last_mut() {
+            Some((prev, prev_style))
+                if *prev_style == style && ch != '\n' && !prev.ends_with('\n') =>
+            {
+                prev.push(ch)
+            }
+            _ => merged.push((String::from(ch), style)),
+        }
+    }
+
+    let mut fountain = String::new();
+    for (text, style) in merged {
+        let escaped = escape(&text);
+        let inner = escaped.trim();
+        if style == Style::default() || inner.is_empty() {
+            fountain.push_str(&escaped);
+            continue;
+        }
+        let stars = match (style.bold, style.italic) {
+            (true, true) => "***",
+            (true, false) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        };
+        let underline = if style.underline { "_" } else { "" };
+        let lead = &escaped[..escaped.len() - escaped.trim_start().len()];
+        let trail = &escaped[escaped.trim_end().len()..];
+        fountain.push_str(&format!(
+            "{}{}{}{}{}{}{}",
+            lead, underline, stars, inner, stars, underline, trail
+        ));
+    }
+
+    fountain
+}
+
+// Whether the style can change between two characters: emphasis starts
+// after whitespace or punctuation and ends before it, and markers of two
+// styles can't touch:
+fn can_change_style((a, a_style): (char, Style), (b, b_style): (char, Style)) -> bool {
+    let plain = Style::default();
+    if a.is_whitespace() || b.is_whitespace() {
+        return true;
+    }
+    match (a_style == plain, b_style == plain) {
+        _ if a_style == b_style => false,
+        (false, true) => b.is_ascii_punctuation(),
+        (true, false) => a.is_ascii_punctuation(),
+        _ => false,
+    }
+}
+
+// The text of a note on a single line, escaped:
+fn note_text(text: &str) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    escape(&words.join(" "))
+}
@@ -0,0 +1,195 @@
+use crate::markup::{Element, ElmType, Spans, Style};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A line of text laid out in a monospaced font, `indent` columns right of
+/// the left margin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Line {
+    pub indent: usize,
+    pub runs: Vec<(String, Style)>,
+}
+
+impl Line {
+    /// The width of the line in columns, not counting its indent.
+    pub fn width(&self) -> usize {
+        self.runs.iter().map(|(text, _)| text.chars().count()).sum()
+    }
+}
+
+/// The printed lines of a block element.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub elm_type: ElmType,
+    /// Where the element starts in the document text.
+    pub offset: usize,
+    /// How many blank lines go between this block and the one before it,
+    /// unless it starts a page.
+    pub blank_before: usize,
+    pub lines: Vec<Line>,
+}
+
+/// A document laid out in lines, before it's split in pages.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    /// The title page keys printed in the middle of the title page, like the
+    /// title and the authors.
+    pub title: Vec<Line>,
+    /// The title page keys printed at its bottom left, like the contact
+    /// details.
+    pub contact: Vec<Line>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// Where the lines of each type go, in columns from the left margin, and how
+// wide they can get on a page `columns` wide. `None` for the types that
+// aren't printed:
+fn placement(elm_type: ElmType, columns: usize) -> Option<(usize, usize, Align)> {
+    match elm_type {
+        ElmType::Action | ElmType::Heading => Some((0, columns, Align::Left)),
+        ElmType::Character => Some((22, 38, Align::Left)),
+        ElmType::Parenthetical => Some((16, 25, Align::Left)),
+        ElmType::Dialogue | ElmType::Lyrics => Some((10, 35, Align::Left)),
+        ElmType::Transition => Some((0, columns, Align::Right)),
+        ElmType::Centered => Some((0, columns, Align::Center)),
+        ElmType::PageBreak => Some((0, 0, Align::Left)),
+        _ => None,
+    }
+}
+
+/// Lays out the document with the given `text` and `blocks` in the standard
+/// screenplay format, on pages `columns` wide, see `PageGeometry`.
+pub fn layout(text: &str, blocks: Spans, columns: usize) -> Layout {
+    let mut layout = Layout::default();
+    let mut title_key = "";
+    let mut prev_end = None;
+    for element in blocks {
+        let elm_type = element.elm_type();
+        if let ElmType::TitleKey | ElmType::TitleValue = elm_type {
+            title_key = element.title_key().unwrap_or(title_key);
+            layout_title_line(&mut layout, title_key, &element, columns);
+            continue;
+        }
+        let Some((indent, width, align)) = placement(elm_type, columns) else {
+            continue;
+        };
+
+        // Lines keep the paragraphs they're in, leaving out the ones that
+        // aren't printed:
+        let blank_before = match prev_end {
+            Some(end) => text
+                .get(end..element.offset())
+                .is_none_or(|gap| gap.matches('\n').count() > 1),
+            None => false,
+        };
+        prev_end = Some(element.offset() + element.len());
+
+        let mut lines = wrap(&element, width);
+        for line in &mut lines {
+            for (text, style) in &mut line.runs {
+                match elm_type {
+                    ElmType::Heading => *text = text.to_uppercase(),
+                    ElmType::Lyrics => style.italic = true,
+                    _ => {}
+                }
+            }
+            line.indent = match align {
+                Align::Left => indent,
+                Align::Center => indent + width.saturating_sub(line.width()) / 2,
+                Align::Right => indent + width.saturating_sub(line.width()),
+            };
+        }
+        layout.blocks.push(Block {
+            elm_type,
+            offset: element.offset(),
+            blank_before: usize::from(blank_before),
+            lines,
+        });
+    }
+
+    layout
+}
+
+/// Whether the title page entry with `key` is centered on the page, like the
+/// title and the authors, rather than at its bottom left, like the contact
+/// details.
+pub fn is_centered_key(key: &str) -> bool {
+    ["title", "credit", "author", "authors", "source"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(key))
+}
+
+// Title page entries are separated by a blank line:
+fn layout_title_line(layout: &mut Layout, key: &str, element: &Element, columns: usize) {
+    let centered = is_centered_key(key);
+    let lines = if centered {
+        &mut layout.title
+    } else {
+        &mut layout.contact
+    };
+    if element.elm_type() == ElmType::TitleKey && !lines.is_empty() {
+        lines.push(Line::default());
+    }
+
+    for mut line in wrap(element, columns) {
+        if centered {
+            line.indent = columns.saturating_sub(line.width()) / 2;
+        }
+        lines.push(line);
+    }
+}
+
+// Splits the text of `element` in lines of at most `width` columns, breaking
+// at spaces where possible:
+fn wrap(element: &Element, width: usize) -> Vec<Line> {
+    let chars: Vec<(char, Style)> = element
+        .runs()
+        .iter()
+        .flat_map(|run| run.text().chars().map(move |ch| (ch, run.style())))
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut rest = &chars[..];
+    while !rest.is_empty() && width > 0 {
+        let len = if rest.len() <= width {
+            rest.len()
+        } else {
+            match rest[..=width].iter().rposition(|&(ch, _)| ch == ' ') {
+                Some(space) if space > 0 => space,
+                _ => width,
+            }
+        };
+        lines.push(line_of(&rest[..len]));
+        rest = &rest[len..];
+        while let [(' ', _), tail @ ..] = rest {
+            rest = tail;
+        }
+    }
+
+    lines
+}
+
+fn line_of(chars: &[(char, Style)]) -> Line {
+    let mut line = Line::default();
+    for &(ch, style) in chars {
+        match line.runs.last_mut() {
+            Some((text, run_style)) if *run_style == style => text.push(ch),
+            _ => line.runs.push((String::from(ch), style)),
+        }
+    }
+    // Trailing spaces don't show, but an underline under them would:
+    if let Some((text, _)) = line.runs.last_mut() {
+        text.truncate(text.trim_end().len());
+    }
+    line.runs.retain(|(text, _)| !text.is_empty());
+
+    line
+}
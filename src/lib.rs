@@ -1,21 +1,78 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod borrowing;
+mod building;
+mod diagnostics;
+mod events;
+mod exporting;
+mod fdx;
+mod html;
+mod identity;
+mod importing;
+mod layout;
 mod markup;
+mod osf;
+mod paging;
 mod parsing;
+mod pdf;
+mod query;
+#[cfg(feature = "std")]
 mod reading;
+mod screenplay;
+#[cfg(feature = "serde")]
+mod serialization;
+mod text;
 mod util;
+mod writing;
+mod xml;
+#[cfg(feature = "fadein")]
+mod zip;
 
 #[cfg(test)]
 mod tests;
 
+pub use borrowing::DocumentRef;
+pub use building::ScriptBuilder;
+pub use diagnostics::{Diagnostic, Severity};
+pub use events::{Event, Events, Tag};
+pub use html::{HtmlOptions, SCREENPLAY_CSS};
+pub use identity::ElementId;
+pub use importing::ImportError;
+pub use layout::Line;
 pub use markup::*;
+pub use paging::{Page, PageGeometry, Pagination};
+pub use pdf::{PaperSize, PdfOptions};
+pub use query::{Match, Matches, Query};
+#[cfg(feature = "std")]
+pub use reading::Parser;
+pub use screenplay::Screenplay;
+pub use writing::Format;
 
-use parsing::{parse_file, parse_reader, parse_str, IntElement};
+use fdx::read_fdx;
+use identity::{carry_ids, IdGenerator};
+#[cfg(feature = "fadein")]
+use osf::read_fadein;
+use osf::read_osf;
+#[cfg(feature = "std")]
+use parsing::parse_parallel;
+use parsing::{parsable_len, parse_str, reparse_bounds, unclosed_boneyard, IntElement};
+#[cfg(feature = "std")]
+use reading::parse_reader;
 
-use std::fs::File;
-use std::io;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::{fs::File, io};
 
+#[derive(Clone, Debug)]
 pub struct Document {
     text: String,
     markup: Vec<IntElement>,
+    diagnostics: Vec<Diagnostic>,
+    ids: IdGenerator,
 }
 
 impl Document {
@@ -23,27 +80,240 @@ impl Document {
         Document {
             text: String::new(),
             markup: Vec::new(),
+            diagnostics: Vec::new(),
+            ids: IdGenerator::new(),
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn from_file(file: File) -> io::Result<Self> {
-        let (markup, text) = parse_file(file)?;
+        let metadata = file.metadata()?;
 
-        Ok(Document { text, markup })
+        parse_reader(file, metadata.len() as usize)
     }
 
+    #[cfg(feature = "std")]
     pub fn from_reader<R>(reader: R) -> io::Result<Self>
     where
         R: io::Read,
     {
-        let (markup, text) = parse_reader(reader)?;
+        parse_reader(reader, 0)
+    }
+
+    /// Reads and parses a document from a `futures` AsyncRead, without
+    /// depending on any particular async runtime.
+    #[cfg(feature = "futures-io")]
+    pub async fn from_async_reader<R>(reader: R) -> io::Result<Self>
+    where
+        R: futures_io::AsyncRead + Unpin,
+    {
+        reading::parse_async_reader(reader).await
+    }
+
+    /// Reads and parses a document from a tokio AsyncRead.
+    #[cfg(feature = "tokio")]
+    pub async fn from_tokio_reader<R>(reader: R) -> io::Result<Self>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        reading::parse_tokio_reader(reader).await
+    }
+
+    /// Parses `text` on up to `threads` threads, or as many as the machine
+    /// can run at once if `threads` is 0. The document is the same as the
+    /// one `Document::from()` returns. Each thread gets at least 256 KiB of
+    /// the text, so shorter texts are parsed on the calling thread.
+    #[cfg(feature = "std")]
+    pub fn from_parallel(text: impl Into<String>, threads: usize) -> Self {
+        let text = text.into();
+        let threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads => threads,
+        };
+        let (markup, diagnostics) = parse_parallel(&text, threads);
+
+        Self::with_markup(text, markup, diagnostics)
+    }
 
-        Ok(Document { text, markup })
+    /// Reads a Final Draft (.fdx) file into a document, with Fountain text
+    /// that parses back to the same types. Styles, scene numbers and dual
+    /// dialogue are kept, and script notes become Fountain notes.
+    pub fn from_fdx(xml: &str) -> Result<Self, ImportError> {
+        Ok(Self::from(read_fdx(xml)?))
     }
 
-    pub fn elements(&self) -> Elements {
+    /// Reads an Open Screenplay Format file into a document, like
+    /// `from_fdx()`.
+    pub fn from_osf(xml: &str) -> Result<Self, ImportError> {
+        Ok(Self::from(read_osf(xml)?))
+    }
+
+    /// Reads a Fade In (.fadein) file, a zip archive with the document in
+    /// Open Screenplay Format, see `from_osf()`.
+    #[cfg(feature = "fadein")]
+    pub fn from_fadein(bytes: &[u8]) -> Result<Self, ImportError> {
+        Ok(Self::from(read_fadein(bytes)?))
+    }
+
+    fn with_markup(
+        text: String,
+        mut markup: Vec<IntElement>,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Self {
+        let mut ids = IdGenerator::new();
+        ids.assign(&mut markup);
+
+        Document {
+            text,
+            markup,
+            diagnostics,
+            ids,
+        }
+    }
+
+    /// Replaces the bytes in `range` with `replacement` and parses only the
+    /// paragraphs affected by the change.
+    ///
+    /// Returns the range of indices into `elements()` that were parsed again,
+    /// or `None` if `range` is out of bounds or not on character boundaries.
+    /// Elements parsed again keep their id if their text is unchanged or only
+    /// lightly edited, see `ElementId`.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Option<Range<usize>> {
+        if range.start > range.end
+            || !self.text.is_char_boundary(range.start)
+            || !self.text.is_char_boundary(range.end)
+        {
+            return None;
+        }
+        let old_len = self.text.len();
+        let touches_boneyard = |s: &str| s.contains("/*") || s.contains("*/");
+        let edits_boneyard =
+            touches_boneyard(&self.text[range.clone()]) || touches_boneyard(replacement);
+        let old_unclosed = unclosed_boneyard(&self.text);
+        let removed = self.text[range.clone()].to_string();
+
+        self.text.replace_range(range.clone(), replacement);
+        let inserted = range.start..range.start + replacement.len();
+        let (from, to) = reparse_bounds(&self.text, inserted.start, inserted.end);
+        let old_to = to + old_len - self.text.len();
+        // The old text from `from` up to the end of `part`:
+        let old_text = |part: Range<usize>| {
+            let text = &self.text;
+            [
+                &text[part.start..range.start],
+                &removed,
+                &text[inserted.end..part.end],
+            ]
+            .concat()
+        };
+
+        // Boneyard can span paragraphs, so any change near it needs a full
+        // parse, and so does text past what offsets can hold:
+        let boneyard_near = edits_boneyard
+            || parsable_len(&self.text, 0) < self.text.len()
+            || touches_boneyard(&self.text[from..to])
+            || self
+                .markup
+                .iter()
+                .any(|e| e.elm_type == ElmType::Boneyard && e.start() < old_to && e.end() >= from)
+            || old_unclosed.is_some_and(|start| start < old_to)
+            || unclosed_boneyard(&self.text).is_some_and(|start| start < to);
+        if boneyard_near {
+            let (mut markup, diagnostics) = parse_str(&self.text, 0);
+            let old_text = old_text(0..self.text.len());
+            carry_ids(&old_text, 0, &self.markup, &self.text, 0, &mut markup);
+            self.ids.assign(&mut markup);
+            (self.markup, self.diagnostics) = (markup, diagnostics);
+            return Some(0..self.markup.len());
+        }
+
+        let first = self.markup.partition_point(|e| e.start() < from);
+        let last = self.markup.partition_point(|e| e.start() < old_to);
+        let (mut new_markup, mut new_diagnostics) = parse_str(&self.text[from..to], from);
+        let old_markup = &self.markup[first..last];
+        let old_text = old_text(from..to);
+        carry_ids(
+            &old_text,
+            from,
+            old_markup,
+            &self.text[from..to],
+            from,
+            &mut new_markup,
+        );
+        self.ids.assign(&mut new_markup);
+        let changed = first..first + new_markup.len();
+        self.markup.splice(first..last, new_markup);
+        for int_elm in &mut self.markup[changed.end..] {
+            int_elm.shift(old_len, self.text.len());
+        }
+
+        self.diagnostics
+            .retain(|d| d.range().start < from || d.range().start >= old_to);
+        for diagnostic in &mut self.diagnostics {
+            if diagnostic.range().start >= old_to {
+                diagnostic.shift(old_len, self.text.len());
+            }
+        }
+        self.diagnostics.append(&mut new_diagnostics);
+        self.diagnostics.sort_by_key(|d| d.range().start);
+
+        Some(changed)
+    }
+
+    // These are also the `Screenplay` methods, here so that they can be
+    // called without importing the trait:
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn elements(&self) -> Elements<'_> {
         Elements::new(self, 0, self.markup.len())
     }
+
+    /// Iterates over the block level elements (headings, action, dialogue...)
+    /// only. Their inline spans are available through `Element::spans()`.
+    pub fn blocks(&self) -> Spans<'_> {
+        Spans::new(&self.text, 0, &self.markup)
+    }
+
+    /// Problems found while parsing the document, ordered by position.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The element with the given id, if it's still in the document. It's
+    /// looked for in every element in turn, so keep the elements instead of
+    /// their ids when looking up many of them.
+    pub fn element(&self, id: ElementId) -> Option<Element<'_>> {
+        let i = self.markup.iter().position(|e| e.id == Some(id))?;
+
+        Elements::new(self, i, i + 1).next()
+    }
+}
+
+impl Screenplay for Document {
+    fn text(&self) -> &str {
+        Document::text(self)
+    }
+
+    fn elements(&self) -> Elements<'_> {
+        Document::elements(self)
+    }
+
+    fn blocks(&self) -> Spans<'_> {
+        Document::blocks(self)
+    }
+
+    fn diagnostics(&self) -> &[Diagnostic] {
+        Document::diagnostics(self)
+    }
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<S> From<S> for Document
@@ -52,30 +322,49 @@ where
 {
     fn from(text: S) -> Self {
         let text = text.into();
-        let markup = parse_str(&text, 0);
+        let (markup, diagnostics) = parse_str(&text, 0);
 
-        Document { text, markup }
+        Self::with_markup(text, markup, diagnostics)
     }
 }
 
+#[derive(Clone)]
 pub struct Elements<'d> {
-    doc: &'d Document,
+    text: &'d str,
+    markup: &'d [IntElement],
     index: usize,
     limit: usize,
 }
 
 impl<'d> Elements<'d> {
+    /// Iterates over the elements of `doc` from index `start` up to `end`,
+    /// clamped to the elements the document has.
     pub fn new(doc: &'d Document, start: usize, end: usize) -> Self {
+        Self::over(&doc.text, &doc.markup, start, end)
+    }
+
+    pub(crate) fn over(text: &'d str, markup: &'d [IntElement], start: usize, end: usize) -> Self {
+        let limit = end.min(markup.len());
         Self {
-            doc,
-            index: start,
-            limit: end,
+            text,
+            markup,
+            index: start.min(limit),
+            limit,
         }
     }
-    fn element_from_internal(&self, int_elm: &IntElement) -> Element<'d> {
-        let text = unsafe { self.doc.text.get_unchecked(int_elm.start..=int_elm.end) };
 
-        Element::new(int_elm.start, int_elm.elm_type, text)
+    fn element_from_internal(&self, i: usize, int_elm: &IntElement) -> Option<Element<'d>> {
+        let text = int_elm.text_in(self.text, 0)?;
+        let rest = self.markup.get(i + 1..)?;
+        let nested = int_elm.nested_count(rest);
+
+        Some(Element::with_markup(
+            int_elm.start(),
+            int_elm.elm_type,
+            text,
+            int_elm.id,
+            &rest[..nested],
+        ))
     }
 }
 
@@ -83,15 +372,17 @@ impl<'d> Iterator for Elements<'d> {
     type Item = Element<'d>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.limit {
+        while self.index < self.limit {
             let i = self.index;
             self.index += 1;
-            self.doc
+            let element = self
                 .markup
                 .get(i)
-                .map(|int_elm| self.element_from_internal(int_elm))
-        } else {
-            None
+                .and_then(|int_elm| self.element_from_internal(i, int_elm));
+            if element.is_some() {
+                return element;
+            }
         }
+        None
     }
 }
@@ -1,4 +1,15 @@
-#[derive(Clone, Copy, Debug, PartialEq)]
+use crate::events::{Event, Tag};
+use crate::identity::ElementId;
+use crate::parsing::{star_count, IntElement};
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum ElmType {
     Action,
     Character,
@@ -11,21 +22,50 @@ pub enum ElmType {
     BoldItalic,
     Underline,
     Boneyard,
+    Section,
+    Synopsis,
+    Note,
+    PageBreak,
+    Centered,
+    Lyrics,
+    /// A `Key: value` line of the title page at the start of a document.
+    TitleKey,
+    /// An indented line continuing the value of the title page key before it.
+    TitleValue,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// A block or span of a document.
+///
+/// Elements compare equal when their type, position, text and spans are,
+/// whatever their ids.
+#[derive(Clone, Debug)]
 pub struct Element<'s> {
     elm_type: ElmType,
     offset: usize,
     text: &'s str,
+    id: Option<ElementId>,
+    // Every span nested inside this element, ordered by position:
+    markup: &'s [IntElement],
 }
 
 impl<'s> Element<'s> {
     pub fn new(offset: usize, elm_type: ElmType, text: &'s str) -> Self {
+        Self::with_markup(offset, elm_type, text, None, &[])
+    }
+
+    pub(crate) fn with_markup(
+        offset: usize,
+        elm_type: ElmType,
+        text: &'s str,
+        id: Option<ElementId>,
+        markup: &'s [IntElement],
+    ) -> Self {
         Self {
             offset,
             text,
             elm_type,
+            id,
+            markup,
         }
     }
 
@@ -41,10 +81,391 @@ impl<'s> Element<'s> {
     pub fn len(&self) -> usize {
         self.text.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+    /// The id this element keeps while the document is edited, `None` for
+    /// elements that aren't part of a `Document` or parsed after it ran out
+    /// of ids, see `ElementId`.
+    pub fn id(&self) -> Option<ElementId> {
+        self.id
+    }
+
+    /// The key of a title page entry, like "Title" in "Title: BIG FISH".
+    pub fn title_key(&self) -> Option<&'s str> {
+        match self.elm_type {
+            ElmType::TitleKey => self.text.split_once(':').map(|(key, _)| key.trim()),
+            _ => None,
+        }
+    }
+
+    /// The number at the end of a scene heading, like "12A" in
+    /// "INT. HOUSE - DAY #12A#".
+    pub fn scene_number(&self) -> Option<&'s str> {
+        match self.elm_type {
+            ElmType::Heading => {
+                let start = scene_number_start(self.text)?;
+                Some(self.text[start..].trim_end().trim_matches('#'))
+            }
+            _ => None,
+        }
+    }
+
+    /// Iterates over the inline spans (bold, italic, underline...) directly
+    /// inside this element. Spans nested deeper can be reached through the
+    /// `spans()` of the returned elements.
+    pub fn spans(&self) -> Spans<'s> {
+        Spans::new(self.text, self.offset, self.markup)
+    }
+
+    /// Copies this element and its spans into an element that doesn't borrow
+    /// from the `Document`, so it can be stored or sent to another thread.
+    pub fn into_owned(self) -> OwnedElement {
+        OwnedElement {
+            elm_type: self.elm_type,
+            offset: self.offset,
+            text: self.text.to_string(),
+            id: self.id,
+            markup: self.markup.to_vec(),
+        }
+    }
+
+    /// Splits the text of this element into runs of equally styled text,
+    /// with emphasis markers, escaping backslashes and boneyard removed.
+    pub fn runs(&self) -> Vec<Run<'s>> {
+        let mut runs = Vec::new();
+        let mut styles = vec![Style::default()];
+        self.walk(&mut |event| match event {
+            Event::Start(Tag::Element(elm_type)) => {
+                let style = styles.last().copied().unwrap_or_default();
+                styles.push(style.with(elm_type));
+            }
+            Event::End(_) => {
+                styles.pop();
+            }
+            Event::Text(text) => {
+                push_run(text, styles.last().copied().unwrap_or_default(), &mut runs);
+            }
+            Event::Start(_) => {}
+        });
+
+        runs
+    }
+
+    /// Visits the start of this element, the text inside it without any
+    /// Fountain syntax and the spans nested in it, and its end.
+    pub(crate) fn walk(&self, visit: &mut impl FnMut(Event<'s>)) {
+        let (mut pos, mut end) = self.markers();
+        if (pos > 0 || end < self.text.len()) && self.elm_type.is_block() {
+            let content = self.text.get(pos..end).unwrap_or("");
+            pos = end - content.trim_start().len();
+            end = pos + content.trim().len();
+        }
+
+        visit(Event::Start(Tag::Element(self.elm_type)));
+        for span in self.spans() {
+            let span_start = span.offset.saturating_sub(self.offset);
+            visit_unescaped(self.text.get(pos..span_start).unwrap_or(""), visit);
+            if !self.hides(span.elm_type) {
+                span.walk(visit);
+            }
+            pos = pos.max(span_start + span.len());
+        }
+        visit_unescaped(self.text.get(pos..end).unwrap_or(""), visit);
+        visit(Event::End(Tag::Element(self.elm_type)));
+    }
+
+    // Boneyard and notes aren't part of the screenplay itself, unless the
+    // element is the note:
+    pub(crate) fn hides(&self, elm_type: ElmType) -> bool {
+        match elm_type {
+            ElmType::Boneyard => true,
+            ElmType::Note => self.elm_type != ElmType::Note,
+            _ => false,
+        }
+    }
+
+    // The range of the text between the Fountain syntax that marks the type
+    // of this element, like the stars around bold text or a forced '!':
+    fn markers(&self) -> (usize, usize) {
+        let text = self.text;
+        let (left, right) = match self.elm_type {
+            ElmType::Bold | ElmType::Italic | ElmType::BoldItalic | ElmType::Underline => {
+                let count = star_count(self.elm_type);
+                let marker = if text.starts_with('_') { '_' } else { '*' };
+                let marked = |s: &str| s.bytes().all(|b| b == marker as u8);
+                match (
+                    text.get(..count),
+                    text.get(text.len().saturating_sub(count)..),
+                ) {
+                    (Some(l), Some(r)) if text.len() >= 2 * count && marked(l) && marked(r) => {
+                        (count, count)
+                    }
+                    _ => (0, 0),
+                }
+            }
+            ElmType::Note if text.starts_with("[[") && text.ends_with("]]") => (2, 2),
+            ElmType::Centered if text.starts_with('>') && text.ends_with('<') => (1, 1),
+            ElmType::PageBreak => (text.len(), 0),
+            ElmType::Section => (text.len() - text.trim_start_matches('#').len(), 0),
+            ElmType::Action if text.starts_with('!') => (1, 0),
+            ElmType::Character if text.starts_with('@') => (1, 0),
+            ElmType::Heading => (
+                usize::from(text.starts_with('.')),
+                scene_number_start(text).map_or(0, |start| text.len() - start),
+            ),
+            ElmType::Transition if text.starts_with('>') => (1, 0),
+            ElmType::Synopsis if text.starts_with('=') => (1, 0),
+            ElmType::Lyrics if text.starts_with('~') => (1, 0),
+            ElmType::TitleKey => {
+                let value = text.split_once(':').map_or("", |(_, value)| value);
+                (text.len() - value.trim_start().len(), 0)
+            }
+            _ => (0, 0),
+        };
+
+        let left = left.min(text.len());
+
+        (left, text.len().saturating_sub(right).max(left))
+    }
+}
+
+/// An `Element` that owns its text, see `Element::into_owned()`.
+#[derive(Clone, Debug)]
+pub struct OwnedElement {
+    elm_type: ElmType,
+    offset: usize,
+    text: String,
+    id: Option<ElementId>,
+    markup: Vec<IntElement>,
 }
 
-impl<'s> std::fmt::Display for Element<'s> {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+impl OwnedElement {
+    pub fn elm_type(&self) -> ElmType {
+        self.elm_type
+    }
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+    pub fn id(&self) -> Option<ElementId> {
+        self.id
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_parts(
+        offset: usize,
+        elm_type: ElmType,
+        text: String,
+        markup: Vec<IntElement>,
+    ) -> Self {
+        Self {
+            elm_type,
+            offset,
+            text,
+            id: None,
+            markup,
+        }
+    }
+
+    /// Borrows this element as an `Element`, which gives access to its spans
+    /// and styled runs.
+    pub fn as_element(&self) -> Element<'_> {
+        Element::with_markup(
+            self.offset,
+            self.elm_type,
+            &self.text,
+            self.id,
+            &self.markup,
+        )
+    }
+}
+
+impl<'s> PartialEq for Element<'s> {
+    fn eq(&self, other: &Self) -> bool {
+        self.elm_type == other.elm_type
+            && self.offset == other.offset
+            && self.text == other.text
+            && self.markup == other.markup
+    }
+}
+
+impl PartialEq for OwnedElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_element() == other.as_element()
+    }
+}
+
+impl<'s> From<Element<'s>> for OwnedElement {
+    fn from(element: Element<'s>) -> Self {
+        element.into_owned()
+    }
+}
+
+impl core::fmt::Display for OwnedElement {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
+        write!(f, "{}", self.text())
+    }
+}
+
+// Drops the backslashes escaping ASCII punctuation:
+fn visit_unescaped<'s>(mut text: &'s str, visit: &mut impl FnMut(Event<'s>)) {
+    let mut visit_text = |text: &'s str| {
+        if !text.is_empty() {
+            visit(Event::Text(Cow::Borrowed(text)));
+        }
+    };
+    while let Some(i) = text.find('\\') {
+        match text.as_bytes().get(i + 1) {
+            Some(b) if b.is_ascii_punctuation() => {
+                visit_text(&text[..i]);
+                visit_text(&text[i + 1..i + 2]);
+                text = &text[i + 2..];
+            }
+            _ => {
+                visit_text(&text[..=i]);
+                text = &text[i + 1..];
+            }
+        }
+    }
+    visit_text(text);
+}
+
+/// Where a scene number like "#12A#" starts at the end of a heading, unless
+/// its first '#' is escaped.
+pub(crate) fn scene_number_start(heading: &str) -> Option<usize> {
+    let inner = heading.trim_end().strip_suffix('#')?;
+    let start = inner.rfind('#')?;
+    let backslashes = inner[..start].len() - inner[..start].trim_end_matches('\\').len();
+    if backslashes % 2 == 1 {
+        return None;
+    }
+    let number = &inner[start + 1..];
+    let valid = !number.is_empty()
+        && number
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '.' | '-'));
+
+    valid.then_some(start)
+}
+
+/// Adds `text` to the last of `runs` if it has the same style, or as a run
+/// of its own.
+pub(crate) fn push_run<'s>(text: Cow<'s, str>, style: Style, runs: &mut Vec<Run<'s>>) {
+    match runs.last_mut() {
+        Some(run) if run.style == style => run.text.to_mut().push_str(&text),
+        _ => runs.push(Run { text, style }),
+    }
+}
+
+/// The emphasis applied to a run of text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl ElmType {
+    /// Whether elements of this type are whole lines, rather than spans of
+    /// text inside a line.
+    pub fn is_block(self) -> bool {
+        !matches!(
+            self,
+            ElmType::Bold
+                | ElmType::Italic
+                | ElmType::BoldItalic
+                | ElmType::Underline
+                | ElmType::Boneyard
+        )
+    }
+}
+
+impl Style {
+    fn with(mut self, elm_type: ElmType) -> Self {
+        match elm_type {
+            ElmType::Bold => self.bold = true,
+            ElmType::Italic => self.italic = true,
+            ElmType::BoldItalic => {
+                self.bold = true;
+                self.italic = true;
+            }
+            ElmType::Underline => self.underline = true,
+            _ => {}
+        }
+        self
+    }
+}
+
+/// A piece of an element's text with a single style, see `Element::runs()`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Run<'s> {
+    pub(crate) text: Cow<'s, str>,
+    pub(crate) style: Style,
+}
+
+impl<'s> Run<'s> {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn style(&self) -> Style {
+        self.style
+    }
+}
+
+/// Iterator over sibling elements, skipping the spans nested inside each of
+/// them.
+#[derive(Clone, Debug)]
+pub struct Spans<'s> {
+    text: &'s str,
+    offset: usize,
+    markup: &'s [IntElement],
+}
+
+impl<'s> Spans<'s> {
+    pub(crate) fn new(text: &'s str, offset: usize, markup: &'s [IntElement]) -> Self {
+        Self {
+            text,
+            offset,
+            markup,
+        }
+    }
+}
+
+impl<'s> Iterator for Spans<'s> {
+    type Item = Element<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((int_elm, rest)) = self.markup.split_first() {
+            let nested = int_elm.nested_count(rest);
+            self.markup = &rest[nested..];
+
+            if let Some(text) = int_elm.text_in(self.text, self.offset) {
+                return Some(Element::with_markup(
+                    int_elm.start(),
+                    int_elm.elm_type,
+                    text,
+                    int_elm.id,
+                    &rest[..nested],
+                ));
+            }
+        }
+        None
+    }
+}
+
+impl<'s> core::fmt::Display for Element<'s> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> Result<(), core::fmt::Error> {
         write!(f, "{}", self.text())
     }
 }
@@ -74,7 +495,10 @@ impl<'s> Slice<'s> {
     pub fn len(&self) -> usize {
         self.text.len()
     }
-    pub fn markup(&self) -> &Vec<Element> {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+    pub fn markup(&self) -> &Vec<Element<'s>> {
         &self.markup
     }
 }
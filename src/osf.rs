@@ -0,0 +1,181 @@
+use crate::exporting::{script_paragraphs, ScriptParagraph};
+use crate::importing::{styled, ImportError, Importer};
+use crate::markup::{ElmType, Run, Spans, Style};
+use crate::xml::{parse_xml, push_escaped, XmlElement};
+#[cfg(feature = "fadein")]
+use crate::zip::{read_zip, write_zip};
+
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "fadein")]
+use alloc::vec::Vec;
+
+// The file in a Fade In archive with the document:
+#[cfg(feature = "fadein")]
+const FADEIN_DOCUMENT: &str = "document.xml";
+
+/// Writes the document with the given `blocks` as an Open Screenplay Format
+/// file, the XML inside Fade In (.fadein) files.
+pub fn write_osf(blocks: Spans) -> String {
+    let (title_page, paragraphs) = script_paragraphs(blocks);
+
+    let mut osf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n");
+    osf.push_str("<document type=\"Open Screenplay Format document\" version=\"40\">\n");
+    if !title_page.is_empty() {
+        osf.push_str("<titlepage>\n");
+        for paragraph in &title_page {
+            let align = if paragraph.centered { "center" } else { "left" };
+            osf.push_str("<para>\n");
+            osf.push_str(&format!(
+                "<style basestyle=\"Action\" align=\"{}\"/>\n",
+                align
+            ));
+            write_runs(&mut osf, &paragraph.runs);
+            osf.push_str("</para>\n");
+        }
+        osf.push_str("</titlepage>\n");
+    }
+    osf.push_str("<paragraphs>\n");
+    for paragraph in &paragraphs {
+        write_paragraph(&mut osf, paragraph);
+    }
+    osf.push_str("</paragraphs>\n");
+    osf.push_str("</document>\n");
+
+    osf
+}
+
+fn write_paragraph(osf: &mut String, paragraph: &ScriptParagraph) {
+    let basestyle = match paragraph.elm_type {
+        ElmType::Heading => "Scene Heading",
+        ElmType::Character => "Character",
+        ElmType::Parenthetical => "Parenthetical",
+        ElmType::Dialogue => "Dialogue",
+        ElmType::Transition => "Transition",
+        _ => "Action",
+    };
+    osf.push_str("<para");
+    if let Some(number) = &paragraph.scene_number {
+        osf.push_str(" number=\"");
+        push_escaped(osf, number);
+        osf.push('"');
+    }
+    if paragraph.new_page {
+        osf.push_str(" page_break=\"1\"");
+    }
+    if paragraph.dual {
+        osf.push_str(" dual=\"1\"");
+    }
+    osf.push_str(">\n");
+    osf.push_str(&format!("<style basestyle=\"{}\"", basestyle));
+    if paragraph.elm_type == ElmType::Centered {
+        osf.push_str(" align=\"center\"");
+    }
+    osf.push_str("/>\n");
+    write_runs(osf, &paragraph.runs);
+    osf.push_str("</para>\n");
+}
+
+fn write_runs(osf: &mut String, runs: &[Run]) {
+    if runs.is_empty() {
+        osf.push_str("<text></text>\n");
+    }
+    for run in runs {
+        let style = run.style();
+        osf.push_str("<text");
+        for (set, name) in [
+            (style.bold, "bold"),
+            (style.italic, "italic"),
+            (style.underline, "underline"),
+        ] {
+            if set {
+                osf.push_str(&format!(" {}=\"1\"", name));
+            }
+        }
+        osf.push('>');
+        push_escaped(osf, run.text());
+        osf.push_str("</text>\n");
+    }
+}
+
+/// Reads an Open Screenplay Format file as Fountain text, see `Importer`.
+pub fn read_osf(xml: &str) -> Result<String, ImportError> {
+    let root = parse_xml(xml)?;
+    if root.name != "document" {
+        return Err(ImportError::UnexpectedRoot(root.name.clone()));
+    }
+
+    let mut importer = Importer::default();
+    if let Some(title_page) = root.child("titlepage") {
+        let paragraphs = title_page.elements().filter(|e| e.name == "para");
+        importer.title_page(paragraphs.map(|paragraph| {
+            let align = paragraph.child("style").and_then(|s| s.attribute("align"));
+            (align == Some("center"), styled_text(paragraph))
+        }));
+    }
+    let paragraphs = root
+        .child("paragraphs")
+        .into_iter()
+        .flat_map(XmlElement::elements);
+    for paragraph in paragraphs.filter(|e| e.name == "para") {
+        let style = paragraph.child("style");
+        let attribute = |name| style.and_then(|s| s.attribute(name));
+        let elm_type = match attribute("basestyle").unwrap_or("") {
+            "Scene Heading" => ElmType::Heading,
+            "Character" => ElmType::Character,
+            "Parenthetical" => ElmType::Parenthetical,
+            "Dialogue" => ElmType::Dialogue,
+            "Transition" => ElmType::Transition,
+            _ if attribute("align") == Some("center") => ElmType::Centered,
+            _ => ElmType::Action,
+        };
+        if paragraph.attribute("page_break") == Some("1") {
+            importer.page_break();
+        }
+        importer.paragraph(
+            elm_type,
+            &styled_text(paragraph),
+            paragraph.attribute("number"),
+            paragraph.attribute("dual") == Some("1"),
+            &[],
+        );
+    }
+
+    Ok(importer.finish())
+}
+
+// The text of the <text> elements of a paragraph as Fountain:
+fn styled_text(paragraph: &XmlElement) -> String {
+    let runs = paragraph
+        .elements()
+        .filter(|e| e.name == "text")
+        .map(|text| {
+            let set = |name| text.attribute(name) == Some("1");
+            let style = Style {
+                bold: set("bold"),
+                italic: set("italic"),
+                underline: set("underline"),
+            };
+            (text.text(), style)
+        });
+
+    styled(runs)
+}
+
+/// Writes the document with the given `blocks` as a Fade In (.fadein) file,
+/// a zip archive with the Open Screenplay Format file in it.
+#[cfg(feature = "fadein")]
+pub fn write_fadein(blocks: Spans) -> Vec<u8> {
+    let osf = write_osf(blocks);
+
+    write_zip(&[(FADEIN_DOCUMENT, osf.as_bytes())])
+}
+
+/// Reads a Fade In (.fadein) file as Fountain text, see `read_osf()`.
+#[cfg(feature = "fadein")]
+pub fn read_fadein(bytes: &[u8]) -> Result<String, ImportError> {
+    let xml = read_zip(bytes, FADEIN_DOCUMENT)?;
+    let xml = core::str::from_utf8(&xml).map_err(|e| ImportError::InvalidXml(e.valid_up_to()))?;
+
+    read_osf(xml)
+}
@@ -0,0 +1,312 @@
+use crate::layout::{Block, Layout, Line};
+use crate::markup::{Element, ElmType, Style};
+use crate::pdf::PaperSize;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::take;
+
+/// The size of the pages a document is split in, see `Screenplay::paginate()`.
+///
+/// Lines are 12pt Courier, 6 to the inch, between 1" margins at the top and
+/// bottom of the page. Columns are 10 to the inch, between the 1.5" left
+/// margin and the 1" right margin: 60 on US Letter and 57 on A4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageGeometry {
+    pub lines_per_page: usize,
+    /// How wide the lines of action get. Dialogue and the other indented
+    /// types keep their widths.
+    pub columns: usize,
+}
+
+impl From<PaperSize> for PageGeometry {
+    fn from(paper: PaperSize) -> Self {
+        let (width, height) = paper.size();
+
+        Self {
+            lines_per_page: ((height - 2.0 * 72.0) / 12.0) as usize,
+            columns: ((width - 2.5 * 72.0) * 10.0 / 72.0) as usize,
+        }
+    }
+}
+
+impl Default for PageGeometry {
+    fn default() -> Self {
+        PaperSize::default().into()
+    }
+}
+
+/// The pages of a document, see `Screenplay::paginate()`.
+///
+/// Pages are numbered from 1, leaving out the title page. Speeches that
+/// don't fit at the bottom of a page end with "(MORE)" and go on the next
+/// one under the character's name with "(CONT'D)", and headings and
+/// character cues are never left alone at the bottom of a page.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pagination {
+    pages: Vec<Page>,
+    // The page each printed element starts on, by offset:
+    starts: Vec<(usize, usize)>,
+    // The offset of the element the first line of each page after the first
+    // belongs to:
+    breaks: Vec<usize>,
+}
+
+/// The lines of a page, `None` for the blank ones.
+pub type Page = Vec<Option<Line>>;
+
+impl Pagination {
+    /// How many pages there are, not counting the title page.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// The laid out lines of every page, starting with page 1.
+    pub fn pages(&self) -> &[Page] {
+        &self.pages
+    }
+
+    /// The number of the page `element` starts on. Elements that aren't
+    /// printed, like notes, are on the page of the last printed element
+    /// before them. Those of the title page aren't on any.
+    pub fn page_of(&self, element: &Element) -> Option<usize> {
+        let i = self
+            .starts
+            .partition_point(|&(offset, _)| offset <= element.offset());
+
+        i.checked_sub(1).map(|i| self.starts[i].1)
+    }
+
+    /// Where each page after the first breaks from the one before it: the
+    /// offset in the document text of the element its first line belongs to.
+    /// A page going on with a speech breaks at the dialogue or parenthetical
+    /// it goes on with, and one going on with a long element at the start of
+    /// that element.
+    pub fn breaks(&self) -> &[usize] {
+        &self.breaks
+    }
+}
+
+/// Splits the laid out `blocks` in pages.
+pub(crate) fn paginate(blocks: &[Block], geometry: PageGeometry) -> Pagination {
+    let mut paginator = Paginator {
+        // A page needs room for a character cue, a line of their speech and
+        // "(MORE)" to always make progress:
+        lines_per_page: geometry.lines_per_page.max(3),
+        pagination: Pagination::default(),
+        page: Vec::new(),
+    };
+
+    let mut i = 0;
+    while i < blocks.len() {
+        let block = &blocks[i];
+        match block.elm_type {
+            ElmType::PageBreak => {
+                paginator.break_page();
+                i += 1;
+            }
+            ElmType::Character => {
+                let speech = blocks[i + 1..]
+                    .iter()
+                    .take_while(|b| {
+                        matches!(b.elm_type, ElmType::Parenthetical | ElmType::Dialogue)
+                    })
+                    .count();
+                paginator.place_speech(&blocks[i..=i + speech]);
+                i += speech + 1;
+            }
+            _ => {
+                // A heading goes on the next page unless the start of what
+                // follows fits under it:
+                if block.elm_type == ElmType::Heading {
+                    let next = blocks
+                        .get(i + 1)
+                        .filter(|next| next.elm_type != ElmType::PageBreak)
+                        .map_or(0, |next| next.blank_before + next.lines.len().min(2));
+                    if paginator.gap(block) + block.lines.len() + next > paginator.room() {
+                        paginator.break_page();
+                    }
+                }
+                paginator.place(block);
+                i += 1;
+            }
+        }
+    }
+    paginator.break_page();
+
+    paginator.pagination
+}
+
+/// Lays out the title page, if the document has one. The title lines start a
+/// third of the way down the page, and the contact lines end at its bottom.
+pub(crate) fn title_page(layout: &Layout, geometry: PageGeometry) -> Option<Page> {
+    if layout.title.is_empty() && layout.contact.is_empty() {
+        return None;
+    }
+    let lines_per_page = geometry.lines_per_page;
+    let mut page: Page = vec![None; lines_per_page / 3];
+    page.extend(layout.title.iter().cloned().map(Some));
+    let contact_start = lines_per_page.saturating_sub(layout.contact.len());
+    if page.len() < contact_start {
+        page.resize(contact_start, None);
+    }
+    page.extend(layout.contact.iter().cloned().map(Some));
+
+    Some(page)
+}
+
+struct Paginator {
+    lines_per_page: usize,
+    pagination: Pagination,
+    page: Page,
+}
+
+// A line of a speech, with the offset of its element and whether it's the
+// element's first line:
+struct SpeechLine {
+    elm_type: ElmType,
+    offset: usize,
+    start: bool,
+    line: Line,
+}
+
+impl Paginator {
+    fn room(&self) -> usize {
+        self.lines_per_page.saturating_sub(self.page.len())
+    }
+
+    // Blank lines are left out at the top of a page:
+    fn gap(&self, block: &Block) -> usize {
+        if self.page.is_empty() {
+            0
+        } else {
+            block.blank_before
+        }
+    }
+
+    fn break_page(&mut self) {
+        if !self.page.is_empty() {
+            self.pagination.pages.push(take(&mut self.page));
+        }
+    }
+
+    fn push_gap(&mut self, gap: usize) {
+        if gap + 1 > self.room() {
+            self.break_page();
+        } else {
+            self.page.resize(self.page.len() + gap, None);
+        }
+    }
+
+    fn push_line(&mut self, offset: usize, start: bool, line: Line) {
+        if self.room() == 0 {
+            self.break_page();
+        }
+        let page = self.pagination.pages.len() + 1;
+        if self.page.is_empty() && page > 1 {
+            self.pagination.breaks.push(offset);
+        }
+        if start {
+            self.pagination.starts.push((offset, page));
+        }
+        self.page.push(Some(line));
+    }
+
+    fn place(&mut self, block: &Block) {
+        self.push_gap(self.gap(block));
+        for (i, line) in block.lines.iter().enumerate() {
+            self.push_line(block.offset, i == 0, line.clone());
+        }
+    }
+
+    // Places a character cue and the parentheticals and dialogue after it,
+    // splitting it between pages after a line of dialogue if it doesn't fit:
+    fn place_speech(&mut self, speech: &[Block]) {
+        let mut lines: Vec<SpeechLine> = Vec::new();
+        for block in speech {
+            for (i, line) in block.lines.iter().enumerate() {
+                lines.push(SpeechLine {
+                    elm_type: block.elm_type,
+                    offset: block.offset,
+                    start: i == 0,
+                    line: line.clone(),
+                });
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+        let cue = speech[0].lines.first().cloned().unwrap_or_default();
+
+        let mut gap = self.gap(&speech[0]);
+        loop {
+            if gap + lines.len() <= self.room() {
+                self.push_gap(gap);
+                for line in lines {
+                    self.push_line(line.offset, line.start, line.line);
+                }
+                return;
+            }
+
+            // Leaves room for "(MORE)", and at least the cue and a line of
+            // dialogue on both pages:
+            let fits = self.room().saturating_sub(gap + 1).min(lines.len() - 1);
+            let split = (2..=fits).rev().find(|&n| {
+                lines[n - 1].elm_type == ElmType::Dialogue
+                    && lines[n..].iter().any(|l| l.elm_type == ElmType::Dialogue)
+            });
+            match split {
+                Some(n) => {
+                    let rest = lines.split_off(n);
+                    self.push_gap(gap);
+                    for line in lines {
+                        self.push_line(line.offset, line.start, line.line);
+                    }
+                    self.push_line(speech[0].offset, false, more(&cue));
+                    self.break_page();
+
+                    // The page breaks at the element the speech goes on with:
+                    lines = vec![SpeechLine {
+                        elm_type: ElmType::Character,
+                        offset: rest[0].offset,
+                        start: false,
+                        line: continued(&cue),
+                    }];
+                    lines.extend(rest);
+                }
+                None if !self.page.is_empty() => self.break_page(),
+                // Nowhere to split it, not even on a page of its own:
+                None => {
+                    for line in lines {
+                        self.push_line(line.offset, line.start, line.line);
+                    }
+                    return;
+                }
+            }
+            gap = 0;
+        }
+    }
+}
+
+fn more(cue: &Line) -> Line {
+    Line {
+        indent: cue.indent,
+        runs: vec![(String::from("(MORE)"), Style::default())],
+    }
+}
+
+fn continued(cue: &Line) -> Line {
+    let mut line = cue.clone();
+    let already = line.runs.iter().any(|(text, _)| text.contains("(CONT'D)"));
+    if !already {
+        line.runs
+            .push((String::from(" (CONT'D)"), Style::default()));
+    }
+
+    line
+}
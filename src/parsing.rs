@@ -1,323 +1,637 @@
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::identity::ElementId;
 use crate::markup::ElmType;
-use crate::reading::StringSavingBytesReader;
-use crate::util::is_ascii_char;
+use crate::util::{is_ascii_char, is_continuation_byte};
+
+use alloc::format;
+#[cfg(feature = "std")]
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+pub fn parse_str(text: &str, offset: usize) -> (Vec<IntElement>, Vec<Diagnostic>) {
+    let mut markup = Vec::new();
+    let mut diagnostics = Vec::new();
+    let (text, rest) = text.split_at(parsable_len(text, offset));
+    if !rest.is_empty() {
+        let start = offset + text.len();
+        diagnostics.push(too_long(start..start + rest.len()));
+    }
+    let mut chars = CharParser::new(offset);
+    let mut lines = LineParser::new();
+    for &byte in text.as_bytes() {
+        if let Some(lstat) = chars.push(byte) {
+            lines.push(lstat, text, offset, &mut markup, &mut diagnostics);
+        }
+    }
+    if let Some(lstat) = chars.finish() {
+        lines.push(lstat, text, offset, &mut markup, &mut diagnostics);
+    }
+    lines.finish(text, offset, &mut markup, &mut diagnostics);
+    diagnostics.sort_by_key(|d| d.range().start);
+
+    (markup, diagnostics)
+}
 
-use std::fs::File;
-use std::io;
-use std::io::Read;
+// Chunks shorter than this aren't worth a thread of their own. A chunk of
+// 256 KiB takes about 4 ms to parse, against some 26 µs to spawn and join
+// its thread (see benches/parse.rs), so the thread costs under 1% of the
+// work it does; at 16 KiB it would already be around 10%:
+#[cfg(feature = "std")]
+const MIN_CHUNK_LEN: usize = 256 * 1024;
+
+/// Parses `text` like `parse_str()`, split in up to `threads` chunks that
+/// are parsed at the same time.
+#[cfg(feature = "std")]
+pub fn parse_parallel(text: &str, threads: usize) -> (Vec<IntElement>, Vec<Diagnostic>) {
+    parse_chunked(text, &chunk_starts(text, threads, MIN_CHUNK_LEN))
+}
 
-pub fn parse_str(text: &str, offset: usize) -> Vec<IntElement> {
-    let lines = CharParser::new(text.as_bytes().bytes(), offset).peekable();
+/// Parses the chunks of `text` starting at each of `starts` on a thread of
+/// their own, and joins their markup and diagnostics.
+#[cfg(feature = "std")]
+pub fn parse_chunked(text: &str, starts: &[usize]) -> (Vec<IntElement>, Vec<Diagnostic>) {
+    let ends = starts.iter().skip(1).copied().chain([text.len()]);
+    let chunks: Vec<_> = starts.iter().copied().zip(ends).collect();
+    let parsed: Vec<_> = match chunks.as_slice() {
+        [(start, end)] => vec![parse_str(&text[*start..*end], *start)],
+        _ => std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|&(start, end)| scope.spawn(move || parse_str(&text[start..end], start)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect()
+        }),
+    };
 
-    let markup = parse_lines(lines, text, offset).unwrap(); // No IO errors on in-memory str
+    let mut markup = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (mut chunk_markup, mut chunk_diagnostics) in parsed {
+        markup.append(&mut chunk_markup);
+        diagnostics.append(&mut chunk_diagnostics);
+    }
 
-    markup
+    (markup, diagnostics)
 }
 
-pub fn parse_file(file: File) -> io::Result<(Vec<IntElement>, String)> {
-    let metadata = file.metadata()?;
-    let mut text = String::with_capacity(metadata.len() as usize);
+/// Splits `text` in up to `chunks` chunks of at least `min_len` bytes, that
+/// parse the same on their own as they do as part of the whole text, and
+/// returns where each of them starts.
+///
+/// Every chunk but the first starts right after a blank line outside of
+/// boneyard. The parser starts in the same state there as at the start of
+/// the text, since a blank line ends every paragraph and inline span.
+#[cfg(feature = "std")]
+pub fn chunk_starts(text: &str, chunks: usize, min_len: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let chunk_len = (text.len() / chunks.max(1)).max(min_len).max(1);
+    let mut next = chunk_len;
+    if next >= text.len() {
+        return starts;
+    }
 
-    let markup = unsafe {
-        // Because we're defining it inside this scope, the reader should
-        // always go out of scope before the destination string. That way
-        // it can never try to append to the string after it is dropped:
-        let reader = StringSavingBytesReader::new(file.bytes(), &mut text);
-        let lines = CharParser::new(reader, 0).peekable();
+    // Scans like `CharParser`, but a line only counts as blank if it's all
+    // ASCII whitespace, boneyard included:
+    let (mut prev, mut in_boneyard, mut blank) = (b'\n', false, true);
+    for (i, &byte) in text.as_bytes().iter().enumerate() {
+        if in_boneyard {
+            in_boneyard = !(prev == b'*' && byte == b'/');
+        } else if byte == b'\n' {
+            if blank && i + 1 >= next && i + 1 < text.len() {
+                starts.push(i + 1);
+                next = i + 1 + chunk_len;
+            }
+            blank = true;
+        } else {
+            in_boneyard = prev == b'/' && byte == b'*';
+            blank &= byte.is_ascii_whitespace();
+        }
+        prev = byte;
+    }
 
-        parse_lines(lines, &(text), 0)?
-    };
+    starts
+}
+
+/// Finds the part of `text` that has to be parsed again after the bytes in
+/// `start..end` have changed.
+///
+/// The type of a line depends on whether its neighbours are blank, and the
+/// type of the line before it, so the reparse starts at the paragraph of the
+/// line before `start` and ends with the paragraph of the line after `end`.
+pub fn reparse_bounds(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let mut from = line_start(text, line_start(text, start).saturating_sub(1));
+    while from > 0 && !is_blank(line_at(text, from)) {
+        let prev = line_start(text, from - 1);
+        if is_blank(line_at(text, prev)) {
+            break;
+        }
+        from = prev;
+    }
 
-    Ok((markup, text))
+    let mut to = line_end(text, end);
+    while to < text.len() && !is_blank(line_at(text, to + 1)) {
+        to = line_end(text, to + 1);
+    }
+    if to < text.len() {
+        to += 1;
+    }
+
+    (from, to)
 }
 
-pub fn parse_reader<R>(reader: R) -> io::Result<(Vec<IntElement>, String)>
-where
-    R: Read,
-{
-    let mut text = String::new();
+/// Where the boneyard that's never closed starts, if `text` has one.
+pub fn unclosed_boneyard(text: &str) -> Option<usize> {
+    let mut pos = 0;
+    loop {
+        let start = pos + text.get(pos..)?.find("/*")?;
+        // The star opening a boneyard can also close it, and the slash
+        // closing it can open the next one:
+        match text.get(start + 1..)?.find("*/") {
+            Some(end) => pos = start + 1 + end + 1,
+            None => return Some(start),
+        }
+    }
+}
 
-    let markup = unsafe {
-        // See fn parse_file() about unsafe usage of StringSavingBytesReader
-        let reader = StringSavingBytesReader::new(reader.bytes(), &mut text);
-        let lines = CharParser::new(reader, 0).peekable();
+fn line_start(text: &str, pos: usize) -> usize {
+    text.as_bytes()[..pos]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1)
+}
 
-        parse_lines(lines, &(text), 0)?
-    };
+// The position of the newline ending the line at `pos`, or the text length:
+fn line_end(text: &str, pos: usize) -> usize {
+    text.as_bytes()[pos..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(text.len(), |i| pos + i)
+}
 
-    Ok((markup, text))
+fn line_at(text: &str, pos: usize) -> &[u8] {
+    &text.as_bytes()[pos..line_end(text, pos)]
 }
 
-fn parse_lines<'s, L>(
-    mut lines: std::iter::Peekable<L>,
-    text: impl AsRef<str>,
-    offset: usize,
-) -> io::Result<Vec<IntElement>>
-where
-    L: Iterator<Item = io::Result<LineStatus>>,
-{
-    let mut markup: Vec<IntElement> = Vec::new();
-    let empty = LineStatus::new();
-    let mut prev = LineStatus::new();
-    while let Some(Ok(mut lstat)) = lines.next() {
-        let next = if let Some(Ok(lstat)) = lines.peek() {
-            lstat
-        } else {
-            &empty
-        };
+fn is_blank(line: &[u8]) -> bool {
+    line.iter().all(|b| b.is_ascii_whitespace())
+}
 
-        if let (Some(start), Some(end)) = (lstat.start, lstat.end) {
-            let range = start - offset..=end - offset;
-            let l = text.as_ref().get(range).unwrap();
+/// Classifies lines once the line after them is known, since whether that
+/// line is blank decides between e.g. a character and action.
+pub struct LineParser {
+    prev: LineStatus,
+    pending: Option<LineStatus>,
+}
 
-            let line_type = if l.starts_with("!") {
-                ElmType::Action
-            } else if l.starts_with("@") {
-                ElmType::Character
-            } else if l.starts_with(".") {
-                ElmType::Heading
-            } else if prev.all_whitespace {
-                if next.all_whitespace {
-                    if l.starts_with("INT") || l.starts_with("EXT") || l.starts_with("I/E") {
-                        ElmType::Heading
-                    } else if lstat.all_uppercase && l.ends_with("TO:") {
-                        ElmType::Transition
-                    } else {
-                        ElmType::Action
-                    }
-                } else if lstat.all_uppercase {
-                    ElmType::Character
-                } else {
-                    ElmType::Action
-                }
-            } else if let Some(ElmType::Character)
-            | Some(ElmType::Dialogue)
-            | Some(ElmType::Parenthetical) = prev.line_type
-            {
-                if l.starts_with('(') && l.ends_with(')') {
-                    ElmType::Parenthetical
-                } else {
-                    ElmType::Dialogue
-                }
-            } else {
-                ElmType::Action
-            };
+impl LineParser {
+    pub fn new() -> Self {
+        Self {
+            prev: LineStatus::new(),
+            pending: None,
+        }
+    }
+
+    /// Takes the next line of `text`, whose first byte is at `offset` in the
+    /// document, and appends the markup and diagnostics of the line before it.
+    pub fn push(
+        &mut self,
+        lstat: LineStatus,
+        text: &str,
+        offset: usize,
+        markup: &mut Vec<IntElement>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if let Some(pending) = self.pending.take() {
+            self.classify(pending, &lstat, text, offset, markup, diagnostics);
+        }
+        self.pending = Some(lstat);
+    }
+
+    /// Appends the markup and diagnostics of the last line, at the end of the
+    /// text.
+    pub fn finish(
+        &mut self,
+        text: &str,
+        offset: usize,
+        markup: &mut Vec<IntElement>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if let Some(pending) = self.pending.take() {
+            self.classify(
+                pending,
+                &LineStatus::new(),
+                text,
+                offset,
+                markup,
+                diagnostics,
+            );
+        }
+    }
+
+    fn classify(
+        &mut self,
+        mut lstat: LineStatus,
+        next: &LineStatus,
+        text: &str,
+        offset: usize,
+        markup: &mut Vec<IntElement>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let line = match (lstat.start, lstat.end) {
+            (Some(start), Some(end)) => {
+                let int_elm = IntElement::new(start, end, ElmType::Action);
+                int_elm.text_in(text, offset).map(|l| (start, end, l))
+            }
+            _ => None,
+        };
+        if let Some((start, end, l)) = line {
+            let indented = start > offset
+                && matches!(text.as_bytes().get(start - offset - 1), Some(b' ' | b'\t'));
+            let title_type = classify_title_line(l, indented, start == 0, self.prev.line_type);
+            let line_type = title_type.unwrap_or_else(|| {
+                classify_line(
+                    l,
+                    lstat.all_uppercase,
+                    self.prev.all_whitespace,
+                    self.prev.line_type,
+                    next.all_whitespace,
+                )
+            });
             lstat.line_type = Some(line_type);
             markup.push(IntElement::new(start, end, line_type));
+
+            if line_type == ElmType::Action && l.starts_with('(') && l.ends_with(')') {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    "parenthetical outside of dialogue is read as action",
+                    start..end + 1,
+                ));
+            }
         }
+        diagnostics.append(&mut lstat.diagnostics);
 
         if let Some(mut line_markup) = lstat.markup.take() {
+            // A note on a line of its own is a single element:
+            if let (Some(ElmType::Note), Some(start), Some(end)) =
+                (lstat.line_type, lstat.start, lstat.end)
+            {
+                line_markup.retain(|e| (e.start(), e.end()) != (start, end));
+            }
+            // Spans are pushed as they close, so inner spans come first.
+            // Ordering them by position puts every span right after the
+            // span (or line) it's nested in:
+            line_markup.sort_by(|a, b| a.start().cmp(&b.start()).then(b.end().cmp(&a.end())));
             markup.append(&mut line_markup);
         }
 
-        prev = lstat;
+        self.prev = lstat;
+    }
+}
+
+/// Decides whether the line `l` is part of the title page, which starts with
+/// a `Key: value` line at the very start of the document and goes on while
+/// the lines after it are either more keys or indented values.
+pub fn classify_title_line(
+    l: &str,
+    indented: bool,
+    first_line: bool,
+    prev_type: Option<ElmType>,
+) -> Option<ElmType> {
+    let in_title_page = matches!(prev_type, Some(ElmType::TitleKey | ElmType::TitleValue));
+    if indented {
+        return in_title_page.then_some(ElmType::TitleValue);
     }
+    // Keys are words like "Draft date", with some lowercase letters so that
+    // a first line like "FADE IN:" is still read as a screenplay:
+    let (key, _) = l.split_once(':')?;
+    let is_key = key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key.bytes().all(|b| b.is_ascii_alphabetic() || b == b' ')
+        && key.bytes().any(|b| b.is_ascii_lowercase());
+
+    (is_key && (first_line || in_title_page)).then_some(ElmType::TitleKey)
+}
 
-    Ok(markup)
+/// Decides the type of the line `l`, given whether it contains any lowercase
+/// ASCII letters and whether the lines around it are blank.
+pub fn classify_line(
+    l: &str,
+    all_uppercase: bool,
+    prev_blank: bool,
+    prev_type: Option<ElmType>,
+    next_blank: bool,
+) -> ElmType {
+    if l.starts_with('!') {
+        ElmType::Action
+    } else if l.starts_with('@') {
+        ElmType::Character
+    } else if l.len() >= 3 && l.bytes().all(|b| b == b'=') {
+        ElmType::PageBreak
+    } else if l.starts_with('=') {
+        ElmType::Synopsis
+    } else if l.starts_with('#') {
+        ElmType::Section
+    } else if l.starts_with('~') {
+        ElmType::Lyrics
+    } else if l.starts_with('>') {
+        // An escaped '<' ends a transition, see `force_line()`:
+        if l.len() > 1 && l.ends_with('<') && !l.ends_with("\\<") {
+            ElmType::Centered
+        } else {
+            ElmType::Transition
+        }
+    } else if l.starts_with("[[") && l.ends_with("]]") {
+        ElmType::Note
+    } else if l.starts_with('.') {
+        ElmType::Heading
+    } else if prev_blank {
+        if next_blank {
+            if l.starts_with("INT") || l.starts_with("EXT") || l.starts_with("I/E") {
+                ElmType::Heading
+            } else if all_uppercase && l.ends_with("TO:") {
+                ElmType::Transition
+            } else {
+                ElmType::Action
+            }
+        } else if all_uppercase {
+            ElmType::Character
+        } else {
+            ElmType::Action
+        }
+    } else if let Some(ElmType::Character)
+    | Some(ElmType::Dialogue)
+    | Some(ElmType::Parenthetical) = prev_type
+    {
+        if l.starts_with('(') && l.ends_with(')') {
+            ElmType::Parenthetical
+        } else {
+            ElmType::Dialogue
+        }
+    } else {
+        ElmType::Action
+    }
 }
 
-struct CharParser<B>
-where
-    B: Iterator<Item = io::Result<u8>>,
-{
-    bytes: B,
+/// Scans text byte by byte for line boundaries and inline markup.
+pub struct CharParser {
     offset: usize,
     cstat: CharStatus,
+    lstat: LineStatus,
 }
 
-impl<B> CharParser<B>
-where
-    B: Iterator<Item = io::Result<u8>>,
-{
-    fn new(bytes: B, offset: usize) -> Self {
+impl CharParser {
+    pub fn new(offset: usize) -> Self {
         Self {
-            bytes,
             offset,
             cstat: CharStatus::new(),
+            lstat: LineStatus::new(),
         }
     }
-}
-
-impl<B> Iterator for CharParser<B>
-where
-    B: Iterator<Item = io::Result<u8>>,
-{
-    type Item = io::Result<LineStatus>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Takes the next byte, and returns the line it ends if it's a newline.
+    pub fn push(&mut self, byte: u8) -> Option<LineStatus> {
         let cstat = &mut self.cstat;
-        let mut lstat = LineStatus::new();
-        while let Some(result) = self.bytes.next() {
-            let byte = match result {
-                Ok(byte) => byte,
-                Err(error) => return Some(Err(error)),
-            };
-            let i = self.offset;
-            self.offset += 1;
-            if let Some(start) = cstat.bnyd_start {
-                if cstat.prev == '*' as u8 && byte == '/' as u8 {
-                    lstat.push_markup(IntElement::bnyd(start, i));
-                    cstat.bnyd_start = None;
-                }
-            } else if is_ascii_char(byte) {
+        let lstat = &mut self.lstat;
+        let i = self.offset;
+        self.offset += 1;
+        if let Some(start) = cstat.bnyd_start {
+            if cstat.prev == b'*' && byte == b'/' {
+                lstat.push_markup(IntElement::bnyd(start, i));
+                lstat.end = Some(i);
+                cstat.bnyd_start = None;
+            }
+        } else {
+            let escaped = cstat.escaped;
+            let is_marker = (byte == b'*' || byte == b'_') && !escaped;
+            if cstat.run_len > 0 && !(is_marker && byte == cstat.run_char) {
+                cstat.close_run(byte, lstat);
+            }
+            cstat.escaped = byte == b'\\' && !escaped;
+
+            if is_ascii_char(byte) {
                 let ch = byte as char;
 
                 match ch {
                     '\n' => {
-                        self.cstat.newline_reset();
+                        cstat.close_line(lstat);
+                        cstat.newline_reset();
 
-                        return Some(Ok(lstat));
+                        return Some(core::mem::replace(lstat, LineStatus::new()));
                     }
                     'a'..='z' => lstat.all_uppercase = false,
                     _ => {}
                 }
 
-                if cstat.undl_before && (ch.is_ascii_whitespace() || ch.is_ascii_punctuation()) {
-                    if let Some(start) = cstat.undl_start {
-                        lstat.push_markup(IntElement::undl(start, i - 1));
-                        cstat.undl_start = None;
+                if ch == '[' && cstat.prev == b'[' && !escaped && cstat.note_start.is_none() {
+                    cstat.note_start = Some(i - 1);
+                } else if ch == ']' && cstat.prev == b']' && !escaped {
+                    if let Some(start) = cstat.note_start.take() {
+                        lstat.push_markup(IntElement::new(start, i, ElmType::Note));
                     }
                 }
-                if ch == '_' {
-                    if cstat.undl_start == None && cstat.whspc_before {
-                        cstat.undl_start = Some(i);
-                    } else {
-                        cstat.undl_before = true;
-                    }
-                } else {
-                    cstat.undl_before = false;
-                }
-
-                if ch == '*' {
-                    if cstat.prev == '/' as u8 {
-                        cstat.bnyd_start = Some(i - 1);
-                    }
-                    if cstat.whspc_before {
-                        if cstat.opening_stars_before == 0 {
-                            cstat.opening_stars_before = 1;
-                        }
-                    } else {
-                        if cstat.closing_stars_before == 0 {
-                            cstat.closing_stars_before = 1;
-                        }
-                    }
 
-                    if cstat.opening_stars_before > 0 && cstat.opening_stars_before < 3 {
-                        cstat.opening_stars_before += 1;
+                if ch == '*' && cstat.prev == b'/' && !escaped {
+                    cstat.bnyd_start = Some(i - 1);
+                    cstat.run_len = 0;
+                } else if is_marker {
+                    if cstat.run_len == 0 {
+                        cstat.run_char = byte;
+                        cstat.run_start = i;
+                        cstat.before_run = cstat.prev;
                     }
-                    if cstat.closing_stars_before > 0 && cstat.closing_stars_before < 3 {
-                        cstat.closing_stars_before += 1;
-                    }
-                } else {
-                    if cstat.closing_stars_before > 0
-                        && (ch.is_ascii_whitespace() || ch.is_ascii_punctuation())
-                    {
-                        match cstat {
-                            CharStatus {
-                                closing_stars_before: 3,
-                                boit_start: Some(start),
-                                ..
-                            } => {
-                                lstat.push_markup(IntElement::boit(*start, i - 1));
-                                cstat.boit_start = None;
-                            }
-                            CharStatus {
-                                closing_stars_before: 2 | 3,
-                                bold_start: Some(start),
-                                ..
-                            } => {
-                                lstat.push_markup(IntElement::bold(*start, i - 1));
-                                cstat.bold_start = None;
-                            }
-                            CharStatus {
-                                closing_stars_before: 1 | 2 | 3,
-                                ital_start: Some(start),
-                                ..
-                            } => {
-                                lstat.push_markup(IntElement::ital(*start, i - 1));
-                                cstat.ital_start = None;
-                            }
-                            _ => {}
-                        };
-                    }
-
-                    cstat.opening_stars_before = 0;
-                    cstat.closing_stars_before = 0;
+                    cstat.run_len += 1;
                 }
 
-                if ch.is_ascii_whitespace() {
-                    cstat.whspc_before = true;
-                } else {
-                    cstat.whspc_before = false;
-
+                if !ch.is_ascii_whitespace() {
                     if lstat.all_whitespace {
                         lstat.all_whitespace = false;
                         lstat.start = Some(i);
                     }
                     lstat.end = Some(i);
                 }
+            } else {
+                // Lines start at the first byte of a character and end at
+                // the last one, so they can always be sliced out of the text:
+                if lstat.all_whitespace && !is_continuation_byte(byte) {
+                    lstat.all_whitespace = false;
+                    lstat.start = Some(i);
+                }
+                if !lstat.all_whitespace {
+                    lstat.end = Some(i);
+                }
             }
+        }
 
-            cstat.prev = byte;
+        cstat.prev = byte;
+        None
+    }
+
+    /// Ends the text, returning the last line unless it's blank.
+    pub fn finish(&mut self) -> Option<LineStatus> {
+        if self.cstat.run_len > 0 {
+            self.cstat.close_run(b'\n', &mut self.lstat);
+        }
+        self.cstat.close_line(&mut self.lstat);
+        if let Some(start) = self.cstat.bnyd_start.take() {
+            self.lstat.diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                "boneyard is never closed, hiding the rest of the document",
+                start..start + 2,
+            ));
         }
+        self.cstat.newline_reset();
 
-        return if lstat.all_whitespace {
+        let lstat = core::mem::replace(&mut self.lstat, LineStatus::new());
+        if lstat.all_whitespace && lstat.diagnostics.is_empty() {
             None
         } else {
-            Some(Ok(lstat))
-        };
+            Some(lstat)
+        }
     }
 }
 
 #[derive(Clone, Debug)]
-struct LineStatus {
+pub struct LineStatus {
     all_uppercase: bool,
     all_whitespace: bool,
     start: Option<usize>,
     end: Option<usize>,
     markup: Option<Vec<IntElement>>,
+    diagnostics: Vec<Diagnostic>,
     line_type: Option<ElmType>,
 }
 
 struct CharStatus {
     prev: u8,
     bnyd_start: Option<usize>,
-    ital_start: Option<usize>,
-    bold_start: Option<usize>,
-    boit_start: Option<usize>,
-    undl_start: Option<usize>,
-    undl_before: bool,
-    opening_stars_before: usize,
-    closing_stars_before: usize,
-    whspc_before: bool,
+    note_start: Option<usize>,
+    // Emphasis spans opened on the current line, innermost last:
+    open: Vec<(ElmType, usize)>,
+    // The current run of consecutive '*' or '_' characters:
+    run_char: u8,
+    run_start: usize,
+    run_len: usize,
+    before_run: u8,
+    // Whether the previous character was an unescaped backslash:
+    escaped: bool,
 }
 
 impl CharStatus {
     pub fn new() -> Self {
         Self {
-            prev: '\n' as u8,
+            prev: b'\n',
             bnyd_start: None,
-            ital_start: None,
-            bold_start: None,
-            boit_start: None,
-            undl_start: None,
-            undl_before: false,
-            opening_stars_before: 0,
-            closing_stars_before: 0,
-            whspc_before: false,
+            note_start: None,
+            open: Vec::new(),
+            run_char: 0,
+            run_start: 0,
+            run_len: 0,
+            before_run: b'\n',
+            escaped: false,
+        }
+    }
+
+    // Reports the spans still open at the end of a line, which are dropped:
+    fn close_line(&mut self, lstat: &mut LineStatus) {
+        for (elm_type, start) in self.open.drain(..) {
+            let name = match elm_type {
+                ElmType::Bold => "bold",
+                ElmType::Italic => "italic",
+                ElmType::BoldItalic => "bold italic",
+                _ => "underline",
+            };
+            lstat.diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("{} text is never closed", name),
+                start..start + star_count(elm_type),
+            ));
+        }
+        if let Some(start) = self.note_start.take() {
+            lstat.diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                "note is never closed",
+                start..start + 2,
+            ));
         }
     }
 
     pub fn newline_reset(&mut self) {
-        self.prev = '\n' as u8;
-        self.ital_start = None;
-        self.bold_start = None;
-        self.boit_start = None;
-        self.undl_start = None;
-        self.opening_stars_before = 0;
-        self.closing_stars_before = 0;
-        self.undl_before = false;
-        self.whspc_before = true;
+        self.prev = b'\n';
+        self.note_start = None;
+        self.open.clear();
+        self.run_len = 0;
+        self.escaped = false;
+    }
+
+    // Called with the first byte after a run of markers. A run can close
+    // open spans if it follows a non-whitespace character and is followed by
+    // whitespace or punctuation, and open a span if it's the other way round.
+    fn close_run(&mut self, next: u8, lstat: &mut LineStatus) {
+        let (start, len) = (self.run_start, self.run_len);
+        self.run_len = 0;
+
+        let before_whitespace = is_whitespace(self.before_run);
+        let can_close = !before_whitespace && (is_whitespace(next) || is_punctuation(next));
+        let can_open =
+            (before_whitespace || is_punctuation(self.before_run)) && !is_whitespace(next);
+
+        let mut used = 0;
+        if can_close {
+            while used < len {
+                let found = self.open.iter().rposition(|(elm_type, _)| {
+                    if self.run_char == b'_' {
+                        *elm_type == ElmType::Underline
+                    } else {
+                        *elm_type != ElmType::Underline
+                    }
+                });
+                let Some(index) = found else { break };
+                let (elm_type, span_start) = self.open[index];
+                let needed = star_count(elm_type);
+                if needed > len - used {
+                    break;
+                }
+                used += needed;
+                self.open.remove(index);
+                lstat.push_markup(IntElement::new(span_start, start + used - 1, elm_type));
+            }
+        }
+
+        if used == 0 && can_open {
+            let elm_type = if self.run_char == b'_' {
+                ElmType::Underline
+            } else {
+                match len {
+                    1 => ElmType::Italic,
+                    2 => ElmType::Bold,
+                    _ => ElmType::BoldItalic,
+                }
+            };
+            self.open.push((elm_type, start));
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    byte.is_ascii_whitespace()
+}
+
+fn is_punctuation(byte: u8) -> bool {
+    byte.is_ascii_punctuation()
+}
+
+// The number of marker characters on either side of an emphasis span:
+pub fn star_count(elm_type: ElmType) -> usize {
+    match elm_type {
+        ElmType::BoldItalic => 3,
+        ElmType::Bold => 2,
+        _ => 1,
     }
 }
 
@@ -329,12 +643,13 @@ impl LineStatus {
             start: None,
             end: None,
             markup: None,
+            diagnostics: Vec::new(),
             line_type: None,
         }
     }
 
     fn push_markup(&mut self, markup_span: IntElement) {
-        if let None = self.markup {
+        if self.markup.is_none() {
             self.markup = Some(Vec::new());
         }
 
@@ -346,35 +661,114 @@ impl LineStatus {
     }
 }
 
+// With the `compact` feature offsets are stored in 32 bits, which halves the
+// size of the markup of a document, but limits documents to 4 GiB:
+#[cfg(not(feature = "compact"))]
+type Offset = usize;
+#[cfg(feature = "compact")]
+type Offset = u32;
+
+#[cfg(not(feature = "compact"))]
+fn to_offset(n: usize) -> Offset {
+    n
+}
+// Only called with offsets `parsable_len()` let through:
+#[cfg(feature = "compact")]
+fn to_offset(n: usize) -> Offset {
+    Offset::try_from(n).unwrap_or(Offset::MAX)
+}
+
+/// How much of `text`, starting at byte `offset` of the document, can be
+/// parsed with offsets that fit in an `Offset`: all of it, or with the
+/// `compact` feature, the lines that end before 4 GiB.
+pub fn parsable_len(text: &str, offset: usize) -> usize {
+    let limit = from_offset(Offset::MAX).saturating_sub(offset);
+    if text.len() <= limit {
+        return text.len();
+    }
+    let cut = text.as_bytes()[..limit].iter().rposition(|&b| b == b'\n');
+
+    cut.map_or(0, |i| i + 1)
+}
+
+/// The error for the text in `range`, which `parsable_len()` leaves out.
+pub fn too_long(range: Range<usize>) -> Diagnostic {
+    Diagnostic::new(
+        Severity::Error,
+        "text past 4 GiB isn't parsed with the `compact` feature",
+        range,
+    )
+}
+
+#[cfg(not(feature = "compact"))]
+fn from_offset(n: Offset) -> usize {
+    n
+}
+#[cfg(feature = "compact")]
+fn from_offset(n: Offset) -> usize {
+    usize::try_from(n).unwrap_or(usize::MAX)
+}
+
 #[derive(Clone, Debug)]
 pub struct IntElement {
+    start: Offset,
+    len: Offset,
+    // Given by the Document holding the element:
+    pub id: Option<ElementId>,
     pub elm_type: ElmType,
-    pub start: usize,
-    pub end: usize,
+}
+
+// Ids don't take part in comparisons, an edited document equals the same
+// text parsed from scratch:
+impl PartialEq for IntElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.elm_type == other.elm_type && self.start == other.start && self.len == other.len
+    }
 }
 
 impl IntElement {
+    /// An element from byte `start` up to and including byte `end`.
     pub fn new(start: usize, end: usize, elm_type: ElmType) -> Self {
         Self {
+            start: to_offset(start),
+            len: to_offset(end.saturating_add(1).saturating_sub(start)),
+            id: None,
             elm_type,
-            start,
-            end,
         }
     }
 
     pub fn bnyd(start: usize, end: usize) -> Self {
         Self::new(start, end, ElmType::Boneyard)
     }
-    pub fn ital(start: usize, end: usize) -> Self {
-        Self::new(start, end, ElmType::Italic)
+
+    /// The text of this element, out of `text` starting at `offset` in the
+    /// document. `None` if the element isn't (fully) inside it.
+    pub fn text_in<'s>(&self, text: &'s str, offset: usize) -> Option<&'s str> {
+        let start = self.start().checked_sub(offset)?;
+
+        text.get(start..start.checked_add(from_offset(self.len))?)
     }
-    pub fn bold(start: usize, end: usize) -> Self {
-        Self::new(start, end, ElmType::Bold)
+
+    pub fn start(&self) -> usize {
+        from_offset(self.start)
     }
-    pub fn boit(start: usize, end: usize) -> Self {
-        Self::new(start, end, ElmType::BoldItalic)
+
+    /// The position of the last byte of the element.
+    pub fn end(&self) -> usize {
+        (self.start() + from_offset(self.len)).saturating_sub(1)
+    }
+
+    /// Moves the element after the text before it changed from `old_len` to
+    /// `new_len` bytes.
+    pub fn shift(&mut self, old_len: usize, new_len: usize) {
+        self.start = to_offset(self.start() + new_len - old_len);
     }
-    pub fn undl(start: usize, end: usize) -> Self {
-        Self::new(start, end, ElmType::Underline)
+
+    /// How many of the elements at the start of `rest` are nested inside this
+    /// one.
+    pub fn nested_count(&self, rest: &[IntElement]) -> usize {
+        rest.iter()
+            .take_while(|e| e.start() >= self.start() && e.end() <= self.end())
+            .count()
     }
 }
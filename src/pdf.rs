@@ -0,0 +1,228 @@
+use crate::layout::Layout;
+use crate::paging::{paginate, title_page, Page, PageGeometry};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The paper a PDF is laid out on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PaperSize {
+    /// 8.5" by 11".
+    #[default]
+    Letter,
+    /// 210 by 297 mm.
+    A4,
+}
+
+impl PaperSize {
+    // Width and height in points:
+    pub(crate) fn size(self) -> (f32, f32) {
+        match self {
+            PaperSize::Letter => (612.0, 792.0),
+            PaperSize::A4 => (595.28, 841.89),
+        }
+    }
+}
+
+/// How `Screenplay::to_pdf()` writes a document.
+///
+/// Pages follow the standard screenplay format: 12pt Courier, a 1.5" left
+/// margin and 1" margins elsewhere, with page numbers from the second page
+/// on. A title page, if the document has one, comes first and isn't
+/// numbered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PdfOptions {
+    pub paper: PaperSize,
+}
+
+// Courier is 600 units wide per 1000 of font size, so 10 characters to the
+// inch at 12pt, and the lines are 6 to the inch:
+const FONT_SIZE: f32 = 12.0;
+const CHAR_WIDTH: f32 = 7.2;
+const LINE_HEIGHT: f32 = 12.0;
+const INCH: f32 = 72.0;
+const LEFT_MARGIN: f32 = 1.5 * INCH;
+const TOP_MARGIN: f32 = INCH;
+// Where the baseline is under the top of a line:
+const ASCENT: f32 = 9.0;
+
+// The standard fonts every PDF reader has, so none need to be embedded:
+const FONTS: [&str; 4] = [
+    "Courier",
+    "Courier-Bold",
+    "Courier-Oblique",
+    "Courier-BoldOblique",
+];
+
+/// Writes the document laid out for `options.paper` as a PDF file.
+pub fn write_pdf(layout: &Layout, options: PdfOptions) -> Vec<u8> {
+    let (width, height) = options.paper.size();
+    let geometry = PageGeometry::from(options.paper);
+
+    let mut contents = Vec::new();
+    if let Some(page) = title_page(layout, geometry) {
+        contents.push(draw_page(&page, height));
+    }
+    for (i, page) in paginate(&layout.blocks, geometry)
+        .pages()
+        .iter()
+        .enumerate()
+    {
+        let mut content = draw_page(page, height);
+        // The first page of a screenplay isn't numbered:
+        if i > 0 {
+            let number = format!("{}.", i + 1);
+            let x = width - INCH - CHAR_WIDTH * number.len() as f32;
+            let y = height - INCH / 2.0 - ASCENT;
+            draw_text(&mut content, 0, x, y, &number);
+        }
+        contents.push(content);
+    }
+    if contents.is_empty() {
+        contents.push(String::new());
+    }
+
+    write_objects(&contents, width, height)
+}
+
+// The content stream of a page:
+fn draw_page(page: &Page, height: f32) -> String {
+    let mut content = String::new();
+    for (i, line) in page.iter().enumerate() {
+        let Some(line) = line else {
+            continue;
+        };
+        let y = height - TOP_MARGIN - LINE_HEIGHT * i as f32 - ASCENT;
+        let mut x = LEFT_MARGIN + CHAR_WIDTH * line.indent as f32;
+        for (text, style) in &line.runs {
+            let font = usize::from(style.bold) + 2 * usize::from(style.italic);
+            draw_text(&mut content, font, x, y, text);
+            let run_width = CHAR_WIDTH * text.chars().count() as f32;
+            if style.underline {
+                draw_underline(&mut content, x, y, run_width);
+            }
+            x += run_width;
+        }
+    }
+
+    content
+}
+
+fn draw_text(content: &mut String, font: usize, x: f32, y: f32, text: &str) {
+    content.push_str(&format!(
+        "BT /F{} {} Tf {:.2} {:.2} Td (",
+        font + 1,
+        FONT_SIZE,
+        x,
+        y
+    ));
+    for byte in text.chars().map(win_ansi) {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                content.push('\\');
+                content.push(char::from(byte));
+            }
+            b' '..=b'~' => content.push(char::from(byte)),
+            _ => content.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    content.push_str(") Tj ET\n");
+}
+
+fn draw_underline(content: &mut String, x: f32, y: f32, width: f32) {
+    content.push_str(&format!(
+        "0.6 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+        x,
+        y - 1.5,
+        x + width,
+        y - 1.5
+    ));
+}
+
+// The byte for `ch` in the encoding of the standard fonts, or '?' for the
+// characters it doesn't have:
+fn win_ansi(ch: char) -> u8 {
+    match ch {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => ch as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        '„' => 0x84,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '™' => 0x99,
+        '\t' => b' ',
+        _ => b'?',
+    }
+}
+
+// Writes the catalog, the page tree, the fonts and then every page with its
+// content stream, followed by the cross-reference table pointing at each:
+fn write_objects(contents: &[String], width: f32, height: f32) -> Vec<u8> {
+    let first_page = 3 + FONTS.len();
+    let mut objects = Vec::new();
+    objects.push(String::from("<< /Type /Catalog /Pages 2 0 R >>"));
+    let kids: Vec<String> = (0..contents.len())
+        .map(|i| format!("{} 0 R", first_page + 2 * i))
+        .collect();
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        contents.len()
+    ));
+    for font in FONTS {
+        objects.push(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+            font
+        ));
+    }
+    let fonts: Vec<String> = (0..FONTS.len())
+        .map(|i| format!("/F{} {} 0 R", i + 1, 3 + i))
+        .collect();
+    for (i, content) in contents.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << {} >> >> /Contents {} 0 R >>",
+            width,
+            height,
+            fonts.join(" "),
+            first_page + 2 * i + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    // A binary comment after the header tells transfer tools the file isn't
+    // text:
+    let mut pdf = Vec::from(&b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n"[..]);
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+
+    pdf
+}
@@ -0,0 +1,232 @@
+use crate::events::Grouping;
+use crate::markup::{Element, ElmType, Spans};
+
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::once;
+use core::ops::Range;
+
+/// Finds elements of a document by type, speaker, scene and text, see
+/// `Screenplay::query()`.
+///
+/// Every condition given has to hold for an element to match. Without any
+/// `elm_type()`, only block level elements (headings, action, dialogue...)
+/// match, so text inside a span isn't found twice.
+#[derive(Clone, Debug)]
+pub struct Query<'d> {
+    blocks: Spans<'d>,
+    types: Vec<ElmType>,
+    speaker: Option<String>,
+    scene: Option<String>,
+    pattern: Option<Pattern>,
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Substring(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl<'d> Query<'d> {
+    pub(crate) fn new(blocks: Spans<'d>) -> Self {
+        Self {
+            blocks,
+            types: Vec::new(),
+            speaker: None,
+            scene: None,
+            pattern: None,
+        }
+    }
+
+    /// Only matches elements of type `elm_type`, or of any of the types when
+    /// called more than once.
+    pub fn elm_type(mut self, elm_type: ElmType) -> Self {
+        self.types.push(elm_type);
+        self
+    }
+
+    /// Only matches elements in the dialogue of `name`, ignoring case and
+    /// extensions like "(V.O.)".
+    pub fn speaker(mut self, name: &str) -> Self {
+        self.speaker = Some(speaker_name(name).to_lowercase());
+        self
+    }
+
+    /// Only matches elements in scenes whose heading contains `text`,
+    /// ignoring case.
+    pub fn scene(mut self, text: &str) -> Self {
+        self.scene = Some(text.to_lowercase());
+        self
+    }
+
+    /// Only matches elements whose text contains `text`, once for every
+    /// time it does.
+    pub fn contains(mut self, text: &str) -> Self {
+        self.pattern = Some(Pattern::Substring(text.to_string()));
+        self
+    }
+
+    /// Only matches elements whose text matches `regex`, once for every
+    /// match that doesn't overlap the one before.
+    #[cfg(feature = "regex")]
+    pub fn regex(mut self, regex: regex::Regex) -> Self {
+        self.pattern = Some(Pattern::Regex(regex));
+        self
+    }
+
+    // The ranges of the text of `element` that match, relative to it:
+    fn find(&self, element: &Element) -> Vec<Range<usize>> {
+        if self.types.is_empty() && !element.elm_type().is_block()
+            || !self.types.is_empty() && !self.types.contains(&element.elm_type())
+        {
+            return Vec::new();
+        }
+        let text = element.text();
+        match &self.pattern {
+            None => once(0..text.len()).collect(),
+            Some(Pattern::Substring(pattern)) if pattern.is_empty() => once(0..0).collect(),
+            Some(Pattern::Substring(pattern)) => text
+                .match_indices(pattern.as_str())
+                .map(|(start, m)| start..start + m.len())
+                .collect(),
+            #[cfg(feature = "regex")]
+            Some(Pattern::Regex(regex)) => regex.find_iter(text).map(|m| m.range()).collect(),
+        }
+    }
+}
+
+impl<'d> IntoIterator for Query<'d> {
+    type Item = Match<'d>;
+    type IntoIter = Matches<'d>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Matches {
+            query: self,
+            queue: VecDeque::new(),
+            found: VecDeque::new(),
+            grouping: Grouping::default(),
+        }
+    }
+}
+
+/// An element found by a `Query`, with the scene and dialogue it's in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match<'d> {
+    element: Element<'d>,
+    scene: Option<Element<'d>>,
+    speaker: Option<Element<'d>>,
+    range: Range<usize>,
+}
+
+impl<'d> Match<'d> {
+    pub fn element(&self) -> &Element<'d> {
+        &self.element
+    }
+    /// The heading of the scene the element is in.
+    pub fn scene(&self) -> Option<&Element<'d>> {
+        self.scene.as_ref()
+    }
+    /// The name of the character speaking, if the element is part of their
+    /// dialogue, without extensions like "(V.O.)".
+    pub fn speaker(&self) -> Option<&'d str> {
+        self.speaker.as_ref().map(|cue| speaker_name(cue.text()))
+    }
+    /// The character cue starting the dialogue the element is part of.
+    pub fn character(&self) -> Option<&Element<'d>> {
+        self.speaker.as_ref()
+    }
+    /// The bytes of the document text that matched the query's text, or the
+    /// whole element without one.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+/// Iterator over the elements found by a `Query`.
+#[derive(Clone, Debug)]
+pub struct Matches<'d> {
+    query: Query<'d>,
+    // The block being searched, followed by the spans nested in it:
+    queue: VecDeque<Element<'d>>,
+    // The matches in the element searched last that are still to come:
+    found: VecDeque<Match<'d>>,
+    grouping: Grouping<'d>,
+}
+
+impl<'d> Matches<'d> {
+    fn push_block(&mut self, block: Element<'d>) {
+        if !block.elm_type().is_block() {
+            return;
+        }
+        self.grouping.enter(&block, &mut |_| {});
+
+        push_nested(&block, &mut self.queue);
+        self.queue.push_front(block);
+    }
+
+    fn in_context(&self) -> bool {
+        let speaker = self
+            .grouping
+            .cue
+            .as_ref()
+            .map(|cue| speaker_name(cue.text()));
+        let heading = self.grouping.scene.as_ref().map(|heading| heading.text());
+        let contains = |text: Option<&str>, lowercase: &Option<String>| match lowercase {
+            Some(lowercase) => text.is_some_and(|t| t.to_lowercase().contains(lowercase.as_str())),
+            None => true,
+        };
+        let same_speaker = match &self.query.speaker {
+            Some(name) => speaker.is_some_and(|s| s.to_lowercase() == *name),
+            None => true,
+        };
+
+        same_speaker && contains(heading, &self.query.scene)
+    }
+}
+
+impl<'d> Iterator for Matches<'d> {
+    type Item = Match<'d>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(found) = self.found.pop_front() {
+                return Some(found);
+            }
+            if let Some(element) = self.queue.pop_front() {
+                let offset = element.offset();
+                for range in self.query.find(&element) {
+                    self.found.push_back(Match {
+                        element: element.clone(),
+                        scene: self.grouping.scene.clone(),
+                        speaker: self.grouping.cue.clone(),
+                        range: offset + range.start..offset + range.end,
+                    });
+                }
+                continue;
+            }
+            let block = self.query.blocks.next()?;
+            self.push_block(block);
+            if !self.in_context() {
+                self.queue.clear();
+            }
+        }
+    }
+}
+
+fn push_nested<'d>(element: &Element<'d>, queue: &mut VecDeque<Element<'d>>) {
+    for span in element.spans() {
+        queue.push_back(span.clone());
+        push_nested(&span, queue);
+    }
+}
+
+// The name in a character cue, like "BOB" in "@Bob (V.O.) ^":
+fn speaker_name(cue: &str) -> &str {
+    let name = cue.trim().strip_prefix('@').unwrap_or(cue.trim());
+    let name = name.trim_end().strip_suffix('^').unwrap_or(name);
+    let name = name.split('(').next().unwrap_or(name);
+
+    name.trim()
+}
@@ -1,64 +1,210 @@
-use crate::util::is_ascii_char;
+use crate::parsing::{parsable_len, too_long, CharParser, LineParser};
+use crate::{Document, Elements};
 
 use std::io;
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+use std::{future::poll_fn, pin::Pin};
 
-/* StringSavingBytesReader allows the library to parse the file while it's
- * reading it from disk. A pointless optimization, since fetching a file
- * from storage takes orders of magnitude longer than parsing it.
- */
-pub struct StringSavingBytesReader<R>
-where
-    R: io::Read,
-{
-    bytes: io::Bytes<R>,
-    dest_string: *mut String,
-    byte_buff: Vec<u8>,
+/// Parses Fountain text that arrives in chunks, like a network stream or a
+/// file too large to wait for.
+///
+/// Every call to `feed()` returns the elements that were completed by the
+/// chunk. A line is complete once the line after it has been read, because
+/// whether that one is blank can change its type.
+pub struct Parser {
+    doc: Document,
+    chars: CharParser,
+    lines: LineParser,
+    // The start of a UTF-8 sequence cut off at the end of the last chunk:
+    partial_char: Vec<u8>,
+    // How much of the text has been given to the CharParser:
+    parsed: usize,
+    // Whether `finish()` has ended the input:
+    finished: bool,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            doc: Document::new(),
+            chars: CharParser::new(0),
+            lines: LineParser::new(),
+            partial_char: Vec::new(),
+            parsed: 0,
+            finished: false,
+        }
+    }
+
+    /// Parses the next chunk of bytes. Invalid UTF-8 is replaced with
+    /// U+FFFD before parsing, so offsets always point into `text()`.
+    pub fn feed(&mut self, chunk: &[u8]) -> Elements<'_> {
+        let first = self.doc.markup.len();
+
+        let joined;
+        let mut bytes = if self.partial_char.is_empty() {
+            chunk
+        } else {
+            self.partial_char.extend_from_slice(chunk);
+            joined = std::mem::take(&mut self.partial_char);
+            &joined[..]
+        };
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(valid) => {
+                    self.doc.text.push_str(valid);
+                    break;
+                }
+                Err(error) => {
+                    let (valid, rest) = bytes.split_at(error.valid_up_to());
+                    // The prefix is valid UTF-8, so this never fails:
+                    self.doc
+                        .text
+                        .push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match error.error_len() {
+                        Some(len) => {
+                            self.doc.text.push(char::REPLACEMENT_CHARACTER);
+                            bytes = &rest[len..];
+                        }
+                        None => {
+                            self.partial_char = rest.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.parse_text();
+        self.doc.ids.assign(&mut self.doc.markup[first..]);
+
+        Elements::new(&self.doc, first, self.doc.markup.len())
+    }
+
+    /// Ends the input, returning the elements of the last lines. Once it has
+    /// ended, calling this again returns no elements.
+    pub fn finish(&mut self) -> Elements<'_> {
+        let first = self.doc.markup.len();
+        if self.finished {
+            return Elements::new(&self.doc, first, first);
+        }
+        self.finished = true;
+
+        if !self.partial_char.is_empty() {
+            self.partial_char.clear();
+            self.doc.text.push(char::REPLACEMENT_CHARACTER);
+            self.parse_text();
+        }
+        let doc = &mut self.doc;
+        let (text, markup, diagnostics) = (&doc.text, &mut doc.markup, &mut doc.diagnostics);
+        if let Some(lstat) = self.chars.finish() {
+            self.lines.push(lstat, text, 0, markup, diagnostics);
+        }
+        self.lines.finish(text, 0, markup, diagnostics);
+        if self.parsed < text.len() {
+            diagnostics.push(too_long(self.parsed..text.len()));
+        }
+        doc.ids.assign(&mut doc.markup[first..]);
+
+        Elements::new(&self.doc, first, self.doc.markup.len())
+    }
+
+    /// The text read so far.
+    pub fn text(&self) -> &str {
+        &self.doc.text
+    }
+
+    /// Ends the input and returns the parsed document.
+    pub fn into_document(mut self) -> Document {
+        self.finish();
+
+        self.doc
+    }
+
+    fn parse_text(&mut self) {
+        let doc = &mut self.doc;
+        let (text, markup, diagnostics) = (&doc.text, &mut doc.markup, &mut doc.diagnostics);
+        // Bytes given to the CharParser already stay parsed:
+        let end = parsable_len(text, 0).max(self.parsed);
+        for &byte in &text.as_bytes()[self.parsed..end] {
+            if let Some(lstat) = self.chars.push(byte) {
+                self.lines.push(lstat, text, 0, markup, diagnostics);
+            }
+        }
+        self.parsed = end;
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl<R> StringSavingBytesReader<R>
+/// Feeds everything `reader` produces to a `Parser`, in buffered chunks.
+pub fn parse_reader<R>(mut reader: R, capacity: usize) -> io::Result<Document>
 where
     R: io::Read,
 {
-    // Unsafe because if the reader lives longer than the destination string,
-    // it may try to mutate it after it's been dropped.
-    pub unsafe fn new(bytes: io::Bytes<R>, dest_string: *mut String) -> Self {
-        Self {
-            bytes,
-            dest_string,
-            byte_buff: Vec::new(),
+    let mut parser = Parser::new();
+    parser.doc.text.reserve(capacity);
+
+    let mut buffer = [0; 8 * 1024];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => {
+                parser.feed(&buffer[..len]);
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
         }
     }
 
-    unsafe fn save_byte(&mut self, byte: u8) {
-        // All non-ascii characters are buffered and only parsed
-        // to a String when an ascii character is encountered:
-        if is_ascii_char(byte) {
-            if self.byte_buff.len() > 0 {
-                (*self.dest_string).push_str(&String::from_utf8_lossy(&self.byte_buff));
-                self.byte_buff.clear()
+    Ok(parser.into_document())
+}
+
+/// Like `parse_reader()`, for readers implementing the `futures` AsyncRead.
+#[cfg(feature = "futures-io")]
+pub async fn parse_async_reader<R>(mut reader: R) -> io::Result<Document>
+where
+    R: futures_io::AsyncRead + Unpin,
+{
+    let mut parser = Parser::new();
+
+    let mut buffer = [0; 8 * 1024];
+    loop {
+        match poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buffer)).await {
+            Ok(0) => break,
+            Ok(len) => {
+                parser.feed(&buffer[..len]);
             }
-            (*self.dest_string).push(byte as char);
-        } else {
-            self.byte_buff.push(byte);
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
         }
     }
+
+    Ok(parser.into_document())
 }
 
-impl<R> Iterator for StringSavingBytesReader<R>
+/// Like `parse_reader()`, for readers implementing tokio's AsyncRead.
+#[cfg(feature = "tokio")]
+pub async fn parse_tokio_reader<R>(mut reader: R) -> io::Result<Document>
 where
-    R: io::Read,
+    R: tokio::io::AsyncRead + Unpin,
 {
-    type Item = io::Result<u8>;
+    let mut parser = Parser::new();
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.bytes.next() {
-            Some(Ok(byte)) => {
-                unsafe {
-                    self.save_byte(byte);
-                }
-                Some(Ok(byte))
+    let mut buffer = [0; 8 * 1024];
+    loop {
+        let mut read_buf = tokio::io::ReadBuf::new(&mut buffer);
+        match poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut read_buf)).await {
+            Ok(()) if read_buf.filled().is_empty() => break,
+            Ok(()) => {
+                parser.feed(read_buf.filled());
             }
-            other => other,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
         }
     }
+
+    Ok(parser.into_document())
 }
@@ -0,0 +1,98 @@
+use crate::diagnostics::Diagnostic;
+use crate::events::Events;
+use crate::fdx::write_fdx;
+use crate::html::{write_html, HtmlOptions};
+use crate::layout::layout;
+use crate::markup::Spans;
+#[cfg(feature = "fadein")]
+use crate::osf::write_fadein;
+use crate::osf::write_osf;
+use crate::paging::{paginate, PageGeometry, Pagination};
+use crate::pdf::{write_pdf, PdfOptions};
+use crate::query::Query;
+use crate::text::write_text;
+use crate::writing::{write_fountain, Format};
+use crate::Elements;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// What can be done with a parsed document, owned or borrowed, see
+/// `Document` and `DocumentRef`.
+pub trait Screenplay {
+    fn text(&self) -> &str;
+
+    fn elements(&self) -> Elements<'_>;
+
+    /// Iterates over the block level elements (headings, action, dialogue...)
+    /// only. Their inline spans are available through `Element::spans()`.
+    fn blocks(&self) -> Spans<'_>;
+
+    /// Problems found while parsing the document, ordered by position.
+    fn diagnostics(&self) -> &[Diagnostic];
+
+    /// Starts a search of the elements of the document, see `Query`.
+    fn query(&self) -> Query<'_> {
+        Query::new(self.blocks())
+    }
+
+    /// Iterates over the document as a stream of events, with scenes and
+    /// dialogue blocks grouped between `Start` and `End` events.
+    fn events(&self) -> Events<'_> {
+        Events::new(self.blocks())
+    }
+
+    /// Writes the document as Fountain text, see `Format`.
+    fn to_fountain(&self, format: Format) -> String {
+        write_fountain(self.text(), self.blocks(), format)
+    }
+
+    /// Writes the document as HTML, see `HtmlOptions`.
+    fn to_html(&self, options: HtmlOptions) -> String {
+        write_html(self.blocks(), options)
+    }
+
+    /// Lays out the document as a printable screenplay in a PDF file, see
+    /// `PdfOptions`.
+    fn to_pdf(&self, options: PdfOptions) -> Vec<u8> {
+        let columns = PageGeometry::from(options.paper).columns;
+        write_pdf(&layout(self.text(), self.blocks(), columns), options)
+    }
+
+    /// Writes the document as a Final Draft (.fdx) file. Scenes without a
+    /// number like "#12A#" in their heading are numbered on from the highest
+    /// number before them, skipping those other headings have.
+    fn to_fdx(&self) -> String {
+        write_fdx(self.blocks())
+    }
+
+    /// Writes the document as an Open Screenplay Format file, numbering the
+    /// scenes like `to_fdx()`.
+    fn to_osf(&self) -> String {
+        write_osf(self.blocks())
+    }
+
+    /// Writes the document as a Fade In (.fadein) file, see `to_osf()`.
+    /// Panics if the Open Screenplay Format text is over 4 GiB, more than a
+    /// zip archive holds.
+    #[cfg(feature = "fadein")]
+    fn to_fadein(&self) -> Vec<u8> {
+        write_fadein(self.blocks())
+    }
+
+    /// Writes the document as fixed-width text laid out like `to_pdf()`, for
+    /// previews in a terminal or an email.
+    fn to_text(&self, geometry: PageGeometry) -> String {
+        write_text(
+            &layout(self.text(), self.blocks(), geometry.columns),
+            geometry,
+        )
+    }
+
+    /// Splits the document in pages of the given size, in the same layout as
+    /// `to_pdf()`.
+    fn paginate(&self, geometry: PageGeometry) -> Pagination {
+        let layout = layout(self.text(), self.blocks(), geometry.columns);
+        paginate(&layout.blocks, geometry)
+    }
+}
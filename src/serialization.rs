@@ -0,0 +1,131 @@
+use crate::markup::{Element, ElmType, OwnedElement, Spans};
+use crate::parsing::IntElement;
+use crate::screenplay::Screenplay;
+use crate::{Document, DocumentRef};
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+impl<'s> Serialize for Element<'s> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Element", 4)?;
+        state.serialize_field("type", &self.elm_type())?;
+        state.serialize_field("offset", &self.offset())?;
+        state.serialize_field("text", self.text())?;
+        state.serialize_field("spans", &SpansSeq(self.spans()))?;
+        state.end()
+    }
+}
+
+struct SpansSeq<'s>(Spans<'s>);
+
+impl<'s> Serialize for SpansSeq<'s> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.clone())
+    }
+}
+
+impl Serialize for OwnedElement {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_element().serialize(serializer)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ElementRepr {
+    #[serde(rename = "type")]
+    elm_type: ElmType,
+    offset: usize,
+    text: String,
+    #[serde(default)]
+    spans: Vec<ElementRepr>,
+}
+
+impl ElementRepr {
+    fn flatten_spans(&self, markup: &mut Vec<IntElement>) {
+        for span in &self.spans {
+            let end = span.offset.checked_add(span.text.len());
+            if let Some(end) = end.and_then(|end| end.checked_sub(1)) {
+                markup.push(IntElement::new(span.offset, end, span.elm_type));
+            }
+            span.flatten_spans(markup);
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OwnedElement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = ElementRepr::deserialize(deserializer)?;
+        let mut markup = Vec::new();
+        repr.flatten_spans(&mut markup);
+
+        Ok(OwnedElement::from_parts(
+            repr.offset,
+            repr.elm_type,
+            repr.text,
+            markup,
+        ))
+    }
+}
+
+impl Serialize for Document {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_document(self, serializer)
+    }
+}
+
+impl<'a> Serialize for DocumentRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_document(self, serializer)
+    }
+}
+
+// Both kinds of documents serialize the same way, as a "Document":
+fn serialize_document<S>(doc: &impl Screenplay, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut state = serializer.serialize_struct("Document", 2)?;
+    state.serialize_field("text", doc.text())?;
+    state.serialize_field("elements", &SpansSeq(doc.blocks()))?;
+    state.end()
+}
+
+#[derive(serde::Deserialize)]
+struct DocumentRepr {
+    text: String,
+}
+
+// The elements of a serialized Document are derived from its text, so only
+// the text is read back and parsed again.
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = DocumentRepr::deserialize(deserializer)?;
+
+        Ok(Document::from(repr.text))
+    }
+}
@@ -1,5 +1,7 @@
 use crate::*;
 
+use alloc::collections::BTreeSet;
+
 #[test]
 fn empty_doc() {
     let doc = Document::new();
@@ -114,3 +116,1610 @@ For one night, one night in your entire life, the universe does not revolve arou
     }
     assert_eq!(elements.next(), None);
 }
+
+#[test]
+fn emphasis_spans_nested_in_blocks() {
+    let text = "Said the _**bold** fish_ to *the cat*.
+
+***Loudly*** he left.";
+
+    let doc = Document::from(text);
+    let mut blocks = doc.blocks();
+    if let Some(e) = blocks.next() {
+        assert_eq!(e.elm_type(), ElmType::Action);
+        let mut spans = e.spans();
+        if let Some(s) = spans.next() {
+            assert_eq!(s.text(), "_**bold** fish_");
+            assert_eq!(s.elm_type(), ElmType::Underline);
+            let nested: Vec<_> = s.spans().map(|s| (s.elm_type(), s.text())).collect();
+            assert_eq!(nested, vec![(ElmType::Bold, "**bold**")]);
+        } else {
+            unreachable!();
+        }
+        if let Some(s) = spans.next() {
+            assert_eq!(s.text(), "*the cat*");
+            assert_eq!(s.elm_type(), ElmType::Italic);
+            assert_eq!(s.spans().next(), None);
+        } else {
+            unreachable!();
+        }
+        assert_eq!(spans.next(), None);
+    } else {
+        unreachable!();
+    }
+    if let Some(e) = blocks.next() {
+        assert_eq!(e.elm_type(), ElmType::Action);
+        let spans: Vec<_> = e.spans().map(|s| (s.elm_type(), s.text())).collect();
+        assert_eq!(spans, vec![(ElmType::BoldItalic, "***Loudly***")]);
+    } else {
+        unreachable!();
+    }
+    assert_eq!(blocks.next(), None);
+}
+
+#[test]
+fn emphasis_needs_word_boundaries() {
+    let doc = Document::from("snake_case_name and 2*3*4 stay *plain\n\n_open at end of line_");
+    let spans: Vec<_> = doc
+        .blocks()
+        .flat_map(|e| {
+            e.spans()
+                .map(|s| (s.elm_type(), s.offset()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    assert_eq!(spans, vec![(ElmType::Underline, 39)]);
+}
+
+#[test]
+fn styled_runs_without_markers() {
+    let doc = Document::from("Not \\*this\\* but _**that** one_ /* hidden */ now.");
+    let runs: Vec<_> = doc
+        .blocks()
+        .flat_map(|e| e.runs())
+        .map(|r| (r.text().to_string(), r.style()))
+        .collect();
+
+    let plain = Style::default();
+    let underline = Style {
+        underline: true,
+        ..plain
+    };
+    let bold_underline = Style {
+        bold: true,
+        ..underline
+    };
+    assert_eq!(
+        runs,
+        vec![
+            ("Not *this* but ".to_string(), plain),
+            ("that".to_string(), bold_underline),
+            (" one".to_string(), underline),
+            ("  now.".to_string(), plain),
+        ]
+    );
+}
+
+#[test]
+fn owned_elements_cross_threads() {
+    fn assert_shareable<T: Clone + Send + Sync>() {}
+    assert_shareable::<Document>();
+    assert_shareable::<Elements>();
+    assert_shareable::<Element>();
+    assert_shareable::<OwnedElement>();
+    assert_shareable::<Spans>();
+    assert_shareable::<Run>();
+
+    let doc = std::sync::Arc::new(Document::from("INT. RIVER - DAY\n\nA _big_ fish."));
+    let shared = doc.clone();
+    let owned: Vec<OwnedElement> =
+        std::thread::spawn(move || shared.blocks().map(Element::into_owned).collect())
+            .join()
+            .unwrap();
+
+    assert_eq!(owned.len(), 2);
+    assert_eq!(owned[0].elm_type(), ElmType::Heading);
+    assert_eq!(owned[1].text(), "A _big_ fish.");
+    assert_eq!(owned[1].offset(), 18);
+    let spans: Vec<_> = owned[1].as_element().spans().map(|s| s.text()).collect();
+    assert_eq!(spans, vec!["_big_"]);
+    assert_eq!(owned[1].as_element(), doc.blocks().nth(1).unwrap());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_json_shape() {
+    let doc = Document::from("BIG FISH\nIt's *true*.");
+    let json = serde_json::to_value(&doc).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "text": "BIG FISH\nIt's *true*.",
+            "elements": [
+                {"type": "Character", "offset": 0, "text": "BIG FISH", "spans": []},
+                {"type": "Dialogue", "offset": 9, "text": "It's *true*.", "spans": [
+                    {"type": "Italic", "offset": 14, "text": "*true*", "spans": []}
+                ]}
+            ]
+        })
+    );
+
+    let back: Document = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&back).unwrap(), json);
+
+    let owned: OwnedElement = serde_json::from_value(json["elements"][1].clone()).unwrap();
+    assert_eq!(owned.as_element(), doc.blocks().nth(1).unwrap());
+}
+
+#[test]
+fn edits_match_full_parse() {
+    let text = "INT. HOUSE - DAY
+
+BOB
+Hello there.
+
+Alice waves _back_.
+
+ALICE
+(shouting)
+Hi!";
+    let edits = [
+        (18..21, "ROBERT"),        // Rename a character
+        (27..28, "\n\n"),          // Split a paragraph
+        (34..35, ""),              // Join BOB's dialogue and the action
+        (55..55, "\n\nCUT TO:"),   // Add a transition
+        (0..0, "!"),               // Force the heading to action
+        (48..54, "*frantically*"), // Turn underline into italic
+    ];
+
+    for (range, replacement) in edits {
+        let mut doc = Document::from(text);
+        let changed = doc.edit(range.clone(), replacement).unwrap();
+
+        let mut expected = text.to_string();
+        expected.replace_range(range, replacement);
+        let expected = Document::from(expected);
+        assert_eq!(doc.text(), expected.text());
+        assert_eq!(
+            doc.elements().collect::<Vec<_>>(),
+            expected.elements().collect::<Vec<_>>()
+        );
+        assert!(changed.end <= doc.elements().count());
+    }
+
+    let mut doc = Document::from(text);
+    assert_eq!(doc.edit(57..57, "!"), Some(5..8));
+    assert_eq!(doc.elements().nth(5).unwrap().text(), "!ALICE");
+    assert_eq!(doc.edit(200..201, ""), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn parallel_parse_matches_sequential() {
+    let scene = "INT. HOUSE - DAY
+
+BOB
+(quietly)
+It's *never* been _this_ quiet.
+
+/* A boneyard
+
+spanning paragraphs */
+
+> THE END <
+
+";
+    let text = scene.repeat(20_000);
+    assert!(parsing::chunk_starts(&text, 4, 0).len() == 4);
+
+    let parallel = Document::from_parallel(text.as_str(), 4);
+    let sequential = Document::from(text.as_str());
+    assert!(parallel.elements().eq(sequential.elements()));
+    assert_eq!(parallel.diagnostics(), sequential.diagnostics());
+}
+
+#[test]
+fn borrowed_documents_parse_without_copying() {
+    let bytes = b"INT. RIVER - DAY\n\nA _big_ fish.";
+    let doc = DocumentRef::from_bytes(bytes);
+    assert_eq!(doc.text().as_ptr(), bytes.as_ptr());
+
+    let owned = Document::from("INT. RIVER - DAY\n\nA _big_ fish.");
+    assert!(doc.elements().eq(owned.elements()));
+    assert!(doc.events().eq(owned.events()));
+    assert_eq!(
+        doc.to_fountain(Format::Canonical),
+        owned.to_fountain(Format::Canonical)
+    );
+
+    let invalid = DocumentRef::from_bytes(b"BOB\nHi \xF0\x9F!");
+    assert_eq!(invalid.text(), "BOB\nHi \u{FFFD}!");
+    assert_eq!(
+        invalid.diagnostics(),
+        Document::from(invalid.text()).diagnostics()
+    );
+
+    let ids: Vec<_> = doc.elements().map(|e| e.id()).collect();
+    let mut doc = Document::from(doc);
+    assert_eq!(doc.elements().map(|e| e.id()).collect::<Vec<_>>(), ids);
+    doc.edit(20..21, "").unwrap();
+    assert_eq!(doc.elements().nth(1).unwrap().text(), "A big_ fish.");
+}
+
+// Code written before `Screenplay` reads documents without importing it:
+mod without_screenplay_trait {
+    use crate::Document;
+
+    #[test]
+    fn documents_read_without_the_trait() {
+        let doc = Document::from("INT. RIVER - DAY\n\nA _big_ fish.");
+        assert_eq!(doc.text(), "INT. RIVER - DAY\n\nA _big_ fish.");
+        assert_eq!(doc.elements().count(), 3);
+        assert_eq!(doc.blocks().count(), 2);
+        assert!(doc.diagnostics().is_empty());
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn markup_size() {
+    let size = core::mem::size_of::<parsing::IntElement>();
+    assert_eq!(size, if cfg!(feature = "compact") { 16 } else { 32 });
+}
+
+#[test]
+fn ids_survive_edits() {
+    let text = "INT. HOUSE - DAY
+
+BOB
+Hello there.
+
+Alice waves _back_.";
+    let mut doc = Document::from(text);
+    let ids = |doc: &Document| {
+        doc.elements()
+            .map(|e| (e.text().to_string(), e.id().unwrap()))
+            .collect::<Vec<_>>()
+    };
+    let before = ids(&doc);
+    let id_of = |text: &str| before.iter().find(|(t, _)| t == text).unwrap().1;
+    let unique: BTreeSet<_> = before.iter().map(|(_, id)| *id).collect();
+    assert_eq!(unique.len(), before.len());
+
+    // A new scene before everything else:
+    doc.edit(0..0, "EXT. GARDEN - NIGHT\n\n").unwrap();
+    assert_eq!(&ids(&doc)[1..], &before[..]);
+    let garden = doc.elements().next().unwrap().id().unwrap();
+    assert!(before.iter().all(|(_, id)| *id != garden));
+
+    // Light edits keep the ids, rewrites don't:
+    let find = |doc: &Document, text: &str| {
+        let start = doc.text().find(text).unwrap();
+        start..start + text.len()
+    };
+    doc.edit(find(&doc, "BOB"), "BOBBY").unwrap();
+    doc.edit(find(&doc, "there"), "there, friend").unwrap();
+    let after = ids(&doc);
+    assert_eq!(after[2], ("BOBBY".to_string(), id_of("BOB")));
+    assert_eq!(
+        after[3],
+        ("Hello there, friend.".to_string(), id_of("Hello there."))
+    );
+    doc.edit(find(&doc, "Hello there, friend."), "Goodbye.")
+        .unwrap();
+    assert_ne!(
+        doc.elements().nth(3).unwrap().id(),
+        Some(id_of("Hello there."))
+    );
+
+    // Through a full parse of the document, caused by the boneyard:
+    doc.edit(0..0, "/* draft */\n\n").unwrap();
+    let underline = doc.element(id_of("_back_")).unwrap();
+    assert_eq!(underline.text(), "_back_");
+    assert_eq!(doc.element(garden).unwrap().text(), "EXT. GARDEN - NIGHT");
+    assert_eq!(doc.element(id_of("Hello there.")), None);
+
+    #[cfg(feature = "std")]
+    {
+        let mut parser = Parser::new();
+        parser.feed(text.as_bytes());
+        let streamed = parser.into_document();
+        assert_eq!(ids(&streamed), before);
+    }
+}
+
+#[test]
+fn ids_run_out_without_repeating() {
+    let (mut markup, _) = parsing::parse_str("INT. HOUSE - DAY\n\nBOB\nHello.", 0);
+    let mut ids = identity::IdGenerator::running_out();
+    ids.assign(&mut markup[..1]);
+    let last = markup[0].id;
+    assert!(last.is_some());
+    ids.assign(&mut markup);
+    assert_eq!(markup[0].id, last);
+    assert!(markup[1..].iter().all(|e| e.id.is_none()));
+}
+
+#[test]
+fn query_by_speaker_scene_and_text() {
+    let doc = Document::from(
+        "INT. HOSPITAL - NIGHT
+
+DAD (V.O.)
+Did I ever tell you _the story_?
+
+WILL
+Every story, Dad.
+
+EXT. RIVER - DAY
+
+Will tells the story.
+
+@Dad ^
+That story is true.",
+    );
+    type Found<'d> = (
+        ElmType,
+        Option<&'d str>,
+        Option<&'d str>,
+        std::ops::Range<usize>,
+    );
+    fn found(query: Query) -> Vec<Found> {
+        query
+            .into_iter()
+            .map(|m| {
+                let scene = m.scene().map(|s| s.text());
+                (m.element().elm_type(), scene, m.speaker(), m.range())
+            })
+            .collect()
+    }
+
+    let hospital = Some("INT. HOSPITAL - NIGHT");
+    let river = Some("EXT. RIVER - DAY");
+    assert_eq!(
+        found(doc.query().speaker("dad").contains("story")),
+        vec![
+            (ElmType::Dialogue, hospital, Some("DAD"), (59..64)),
+            (ElmType::Dialogue, river, Some("Dad"), (145..150)),
+        ]
+    );
+    assert_eq!(
+        found(doc.query().scene("river").contains("story")),
+        vec![
+            (ElmType::Action, river, None, (125..130)),
+            (ElmType::Dialogue, river, Some("Dad"), (145..150)),
+        ]
+    );
+    assert_eq!(
+        found(doc.query().elm_type(ElmType::Underline)),
+        vec![(ElmType::Underline, hospital, Some("DAD"), (54..65))]
+    );
+    // Every occurrence in an element is a match of its own:
+    assert_eq!(
+        found(doc.query().speaker("will").contains("ry")),
+        vec![
+            (ElmType::Dialogue, hospital, Some("WILL"), (76..78)),
+            (ElmType::Dialogue, hospital, Some("WILL"), (82..84)),
+        ]
+    );
+    assert_eq!(found(doc.query().elm_type(ElmType::Character)).len(), 3);
+    assert_eq!(found(doc.query().speaker("WILL").scene("river")), vec![]);
+
+    #[cfg(feature = "regex")]
+    {
+        let regex = regex::Regex::new(r"(?i)\bever(y)?\b").unwrap();
+        let ranges: Vec<_> = doc
+            .query()
+            .regex(regex)
+            .into_iter()
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(ranges, vec![40..44, 73..78]);
+    }
+}
+
+#[test]
+fn builder_round_trips_types() {
+    let doc = ScriptBuilder::new()
+        .section(1, "Act *One*")
+        .synopsis("Will learns the truth.")
+        .heading("INT. HOSPITAL - NIGHT")
+        .heading("Flashback")
+        .action("EDWARD sleeps.\nHE SNORES.\n@home, for once.")
+        .action("CUT TO:")
+        .character("McCLANE")
+        .parenthetical("quietly")
+        .dialogue("...is this _it_?\n(not a parenthetical")
+        .dialogue("[[not a note]]")
+        .note("Check [[this]] with Dan")
+        .transition("SMASH CUT TO:")
+        .transition("Fade out.")
+        .transition("BACK TO <")
+        .centered("THE END")
+        .lyrics("Daisy, Daisy\nGive me your answer, do")
+        .page_break()
+        .dialogue("Nobody says this.")
+        .build();
+
+    let types: Vec<_> = doc.blocks().map(|e| e.elm_type()).collect();
+    assert_eq!(
+        types,
+        vec![
+            ElmType::Section,
+            ElmType::Synopsis,
+            ElmType::Heading,
+            ElmType::Heading,
+            ElmType::Action,
+            ElmType::Action,
+            ElmType::Action,
+            ElmType::Action,
+            ElmType::Character,
+            ElmType::Parenthetical,
+            ElmType::Dialogue,
+            ElmType::Dialogue,
+            ElmType::Dialogue,
+            ElmType::Note,
+            ElmType::Transition,
+            ElmType::Transition,
+            ElmType::Transition,
+            ElmType::Centered,
+            ElmType::Lyrics,
+            ElmType::Lyrics,
+            ElmType::PageBreak,
+            ElmType::Action,
+        ]
+    );
+
+    let plain: Vec<String> = doc
+        .blocks()
+        .map(|e| e.runs().iter().map(|r| r.text()).collect())
+        .collect();
+    assert_eq!(plain[0], "Act *One*");
+    assert_eq!(plain[3], "Flashback");
+    assert_eq!(plain[6], "@home, for once.");
+    assert_eq!(plain[8], "McCLANE");
+    assert_eq!(plain[10], "...is this _it_?");
+    assert_eq!(plain[12], "[[not a note]]");
+    assert_eq!(plain[13], "Check [[this]] with Dan");
+    assert_eq!(plain[15], "Fade out.");
+    assert_eq!(plain[16], "BACK TO <");
+    assert_eq!(plain[17], "THE END");
+    // Lines of lyrics stay together in one paragraph:
+    assert!(doc
+        .to_fountain(Format::Lossless)
+        .contains("~Daisy, Daisy\n~Give me your answer, do\n"));
+}
+
+#[test]
+fn canonical_fountain() {
+    let text = "  .INT. HOUSE - DAY\n\n\n\n!BOB waves.   \n@bob\n(to himself)\nHello *there*.\n\n\n>CUT TO:\n\n#Act Two\n\n> THE END<";
+    let doc = Document::from(text);
+
+    assert_eq!(doc.to_fountain(Format::Lossless), text);
+
+    let canonical = doc.to_fountain(Format::Canonical);
+    assert_eq!(
+        canonical,
+        "INT. HOUSE - DAY\n\nBOB waves.\n@BOB\n(to himself)\nHello *there*.\n\nCUT TO:\n\n# Act Two\n\n> THE END <\n"
+    );
+    let reparsed = Document::from(canonical.as_str());
+    let types = |doc: &Document| doc.blocks().map(|e| e.elm_type()).collect::<Vec<_>>();
+    assert_eq!(types(&reparsed), types(&doc));
+    assert_eq!(reparsed.to_fountain(Format::Canonical), canonical);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn push_parser_matches_document() {
+    // Lines ending in, or made only of, characters longer than a byte:
+    let text = "INT. CAFÉ - DAY\n\nZOË\nÇa va?\n\n…\n\nShe leaves. \u{1F41F}";
+
+    let mut parser = Parser::new();
+    let mut streamed = Vec::new();
+    for chunk in text.as_bytes().chunks(3) {
+        streamed.extend(parser.feed(chunk).map(Element::into_owned));
+    }
+    streamed.extend(parser.finish().map(Element::into_owned));
+
+    let doc = Document::from(text);
+    assert_eq!(parser.text(), text);
+    assert_eq!(
+        streamed,
+        doc.elements().map(Element::into_owned).collect::<Vec<_>>()
+    );
+
+    let from_reader = Document::from_reader(text.as_bytes()).unwrap();
+    assert_eq!(from_reader.text(), text);
+    assert_eq!(from_reader.elements().count(), doc.elements().count());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn push_parser_emits_completed_lines() {
+    let mut parser = Parser::new();
+    assert_eq!(parser.feed(b"BOB\nHi").count(), 0);
+    // BOB's type is known once the line after it has started:
+    assert_eq!(parser.feed(b" there.\n").count(), 1);
+    assert_eq!(parser.feed(b"\n").count(), 1);
+    assert_eq!(parser.feed(b"Invalid \xFF byte, cut \xC3").count(), 0);
+    assert_eq!(parser.finish().count(), 1);
+
+    let doc = parser.into_document();
+    assert_eq!(
+        doc.text(),
+        "BOB\nHi there.\n\nInvalid \u{FFFD} byte, cut \u{FFFD}"
+    );
+    let last = doc.elements().last().unwrap();
+    assert_eq!(last.text(), "Invalid \u{FFFD} byte, cut \u{FFFD}");
+
+    // Ending the input again adds nothing, before or in into_document():
+    let text = "Action.\n\n/* Never closed";
+    let mut parser = Parser::new();
+    parser.feed(text.as_bytes()).for_each(drop);
+    assert_eq!(parser.finish().count(), 1);
+    assert_eq!(parser.finish().count(), 0);
+    let doc = parser.into_document();
+    let expected = Document::from(text);
+    assert!(!expected.diagnostics().is_empty());
+    assert_eq!(doc.diagnostics(), expected.diagnostics());
+    assert_eq!(doc.elements().count(), expected.elements().count());
+}
+
+// Polls a future that never has to wait, like reading from a slice.
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+#[test]
+fn async_readers() {
+    let text = "EXT. LAKE - DAY\n\nWILL\n(calling)\nDad?";
+    let expected: Vec<_> = Document::from(text)
+        .elements()
+        .map(Element::into_owned)
+        .collect();
+
+    #[cfg(feature = "futures-io")]
+    {
+        let doc = block_on(Document::from_async_reader(text.as_bytes())).unwrap();
+        assert_eq!(
+            doc.elements().map(Element::into_owned).collect::<Vec<_>>(),
+            expected
+        );
+    }
+    #[cfg(feature = "tokio")]
+    {
+        let doc = block_on(Document::from_tokio_reader(text.as_bytes())).unwrap();
+        assert_eq!(
+            doc.elements().map(Element::into_owned).collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn title_page() {
+    let doc = Document::from(
+        "Title: _**BIG FISH**_
+Credit: Written by
+Authors:
+    John August
+    Daniel Wallace
+
+FADE IN:
+
+Title: not a title page any more.",
+    );
+    let types: Vec<_> = doc
+        .blocks()
+        .map(|e| {
+            let text: String = e.runs().iter().map(|r| r.text()).collect();
+            (e.elm_type(), e.title_key(), text)
+        })
+        .collect();
+
+    assert_eq!(
+        types,
+        vec![
+            (ElmType::TitleKey, Some("Title"), "BIG FISH".to_string()),
+            (ElmType::TitleKey, Some("Credit"), "Written by".to_string()),
+            (ElmType::TitleKey, Some("Authors"), "".to_string()),
+            (ElmType::TitleValue, None, "John August".to_string()),
+            (ElmType::TitleValue, None, "Daniel Wallace".to_string()),
+            (ElmType::Action, None, "FADE IN:".to_string()),
+            (
+                ElmType::Action,
+                None,
+                "Title: not a title page any more.".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        Document::from("FADE IN:\n\nA river.")
+            .blocks()
+            .next()
+            .unwrap()
+            .elm_type(),
+        ElmType::Action
+    );
+
+    let canonical = doc.to_fountain(Format::Canonical);
+    let reparsed = Document::from(canonical.as_str());
+    assert!(reparsed
+        .blocks()
+        .map(|e| e.elm_type())
+        .eq(doc.blocks().map(|e| e.elm_type())));
+}
+
+#[test]
+fn html_fragment() {
+    let doc = Document::from(
+        "Title: Big <Fish>
+Author: John August
+
+INT. RIVER - DAY
+
+Edward's _**first**_ catch. [[Check the fish.]]
+
+EDWARD
+(to himself)
+Got you & *her*.
+
+CUT TO:
+
+## Act Two
+
+===",
+    );
+
+    assert_eq!(
+        doc.to_html(HtmlOptions::default()),
+        r#"<div class="screenplay">
+<section class="title-page">
+<div class="title">
+<p>Big &lt;Fish&gt;</p>
+</div>
+<div class="author">
+<p>John August</p>
+</div>
+</section>
+<section class="scene">
+<h2 class="scene-heading">INT. RIVER - DAY</h2>
+<p class="action">Edward&#39;s <u><strong>first</strong></u> catch. </p>
+<div class="dialogue-block">
+<p class="character">EDWARD</p>
+<p class="parenthetical">(to himself)</p>
+<p class="dialogue">Got you &amp; <em>her</em>.</p>
+</div>
+<p class="transition">CUT TO:</p>
+</section>
+<h2 class="section">Act Two</h2>
+<hr class="page-break">
+</div>
+"#
+    );
+
+    let page = doc.to_html(HtmlOptions {
+        standalone: true,
+        stylesheet: true,
+    });
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("<title>Big &lt;Fish&gt;</title>"));
+    assert!(page.contains(SCREENPLAY_CSS));
+    assert!(page.ends_with("</div>\n</body>\n</html>\n"));
+}
+
+#[test]
+fn pdf_pages() {
+    let mut text = String::from(
+        "Title: Big Fish
+Author: John August
+
+INT. RIVER - DAY
+
+Edward's _first_ catch, a fish as long as his arm and twice as stubborn as any he ever met.
+
+EDWARD
+(to himself)
+Got you (finally).
+",
+    );
+    for i in 0..60 {
+        text.push_str(&format!("\nLine {}.\n", i));
+    }
+    let doc = Document::from(text);
+    // Non-ASCII bytes are replaced one for one, to keep the offsets:
+    let pdf: String = doc
+        .to_pdf(PdfOptions::default())
+        .iter()
+        .map(|&b| if b.is_ascii() { char::from(b) } else { '?' })
+        .collect();
+
+    assert!(pdf.starts_with("%PDF-1.4\n"));
+    assert!(pdf.ends_with("%%EOF\n"));
+    // A title page, then 54 lines to a page:
+    assert!(pdf.contains("/Count 4 "));
+    assert!(pdf.contains("/MediaBox [0 0 612 792]"));
+    assert!(pdf.contains("/BaseFont /Courier "));
+    assert!(pdf.contains("Td (Big Fish) Tj"));
+    // Wrapped at 60 columns, underlined, with the cue and dialogue indented:
+    assert!(pdf.contains("Td (stubborn as any he ever met.) Tj"));
+    assert!(pdf.contains("0.6 w "));
+    assert!(pdf.contains("/F1 12 Tf 266.40 "));
+    assert!(pdf.contains("Td (Got you \\(finally\\).) Tj"));
+    assert!(pdf.contains("Td (2.) Tj"));
+    assert!(!pdf.contains("Td (1.) Tj"));
+
+    // Every object is where the cross-reference table says:
+    let xref = pdf.rfind("startxref\n").unwrap();
+    let start: usize = pdf[xref + 10..].lines().next().unwrap().parse().unwrap();
+    let offsets: Vec<usize> = pdf[start..]
+        .lines()
+        .skip(3)
+        .take_while(|line| line.ends_with(" n "))
+        .map(|line| line[..10].parse().unwrap())
+        .collect();
+    assert_eq!(offsets.len(), 14);
+    for (i, offset) in offsets.iter().enumerate() {
+        assert!(pdf[*offset..].starts_with(&format!("{} 0 obj", i + 1)));
+    }
+
+    let a4 = doc.to_pdf(PdfOptions {
+        paper: PaperSize::A4,
+    });
+    let a4 = String::from_utf8_lossy(&a4);
+    assert!(a4.contains("/MediaBox [0 0 595.28 841.89]"));
+    // The narrower page leaves 57 columns between the margins:
+    assert!(a4.contains("Td (as stubborn as any he ever met.) Tj"));
+    assert_eq!(
+        PageGeometry::from(PaperSize::A4),
+        PageGeometry {
+            lines_per_page: 58,
+            columns: 57,
+        }
+    );
+    assert_eq!(PageGeometry::default().columns, 60);
+}
+
+#[test]
+fn pagination() {
+    let doc = Document::from(
+        "INT. HOUSE - DAY
+
+The door opens.
+
+Bob walks in.
+
+BOB
+(quietly)
+I know you are in here somewhere, and I know you took the last of the milk, so come out now.
+
+INT. KITCHEN - DAY
+
+Empty.
+
+INT. GARDEN - DAY
+
+Nobody.
+
+===
+
+THE END",
+    );
+    let pagination = doc.paginate(PageGeometry {
+        lines_per_page: 10,
+        columns: 60,
+    });
+    let pages: Vec<Vec<String>> = pagination
+        .pages()
+        .iter()
+        .map(|page| {
+            page.iter()
+                .map(|line| match line {
+                    Some(line) => {
+                        let text: String = line.runs.iter().map(|(t, _)| t.as_str()).collect();
+                        format!("{}{}", " ".repeat(line.indent), text)
+                    }
+                    None => String::new(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let cue = |name: &str| format!("{}{}", " ".repeat(22), name);
+    let dialogue = |text: &str| format!("{}{}", " ".repeat(10), text);
+    assert_eq!(
+        pages,
+        vec![
+            vec![
+                "INT. HOUSE - DAY".to_string(),
+                String::new(),
+                "The door opens.".to_string(),
+                String::new(),
+                "Bob walks in.".to_string(),
+                String::new(),
+                cue("BOB"),
+                format!("{}(quietly)", " ".repeat(16)),
+                dialogue("I know you are in here somewhere,"),
+                cue("(MORE)"),
+            ],
+            vec![
+                cue("BOB (CONT'D)"),
+                dialogue("and I know you took the last of the"),
+                dialogue("milk, so come out now."),
+                String::new(),
+                "INT. KITCHEN - DAY".to_string(),
+                String::new(),
+                "Empty.".to_string(),
+            ],
+            // The heading would have been alone at the bottom of the page:
+            vec![
+                "INT. GARDEN - DAY".to_string(),
+                String::new(),
+                "Nobody.".to_string(),
+            ],
+            vec!["THE END".to_string()],
+        ]
+    );
+
+    assert_eq!(pagination.len(), 4);
+    let page_of = |text: &str| {
+        let element = doc.blocks().find(|e| e.text() == text).unwrap();
+        pagination.page_of(&element)
+    };
+    assert_eq!(page_of("Bob walks in."), Some(1));
+    assert_eq!(page_of("INT. KITCHEN - DAY"), Some(2));
+    assert_eq!(page_of("Nobody."), Some(3));
+    let offset = |text: &str| doc.text().find(text).unwrap();
+    assert_eq!(
+        pagination.breaks(),
+        [
+            offset("I know you are"),
+            offset("INT. GARDEN"),
+            offset("THE END")
+        ]
+    );
+}
+
+#[test]
+fn plain_text() {
+    let doc = Document::from(
+        "Title: Big Fish
+
+.int. river - day
+
+Edward's _first_ catch, a fish as long as his arm and twice as stubborn.
+
+EDWARD
+Got you.
+
+CUT TO:
+
+===
+
+THE END",
+    );
+    let title = " ".repeat(26) + "Big Fish";
+    assert_eq!(
+        doc.to_text(PageGeometry {
+            lines_per_page: 9,
+            columns: 60,
+        }),
+        format!(
+            "\n\n\n{}
+\u{c}
+INT. RIVER - DAY
+
+Edward's first catch, a fish as long as his arm and twice as
+stubborn.
+
+                      EDWARD
+          Got you.
+
+                                                     CUT TO:
+\u{c}
+                                                          2.
+
+THE END
+",
+            title
+        )
+    );
+}
+
+#[test]
+fn fdx_export() {
+    let doc = Document::from(
+        "Title: Big Fish
+Author: John August
+Contact: jane@example.com
+
+INT. RIVER - DAY #7A#
+
+Edward's **_first_** catch & release.
+
+EDWARD
+(to himself)
+Got you.
+
+WILL ^
+Dad?
+
+===
+
+EXT. RIVER - NIGHT
+
+~Daisy, Daisy
+
+> THE END <",
+    );
+
+    assert_eq!(
+        doc.to_fdx(),
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<FinalDraft DocumentType="Script" Template="No" Version="5">
+<Content>
+<Paragraph Type="Scene Heading" Number="7A">
+<Text>INT. RIVER - DAY</Text>
+</Paragraph>
+<Paragraph Type="Action">
+<Text>Edward&apos;s </Text>
+<Text Style="Bold+Underline">first</Text>
+<Text> catch &amp; release.</Text>
+</Paragraph>
+<Paragraph>
+<DualDialogue>
+<Paragraph Type="Character">
+<Text>EDWARD</Text>
+</Paragraph>
+<Paragraph Type="Parenthetical">
+<Text>(to himself)</Text>
+</Paragraph>
+<Paragraph Type="Dialogue">
+<Text>Got you.</Text>
+</Paragraph>
+<Paragraph Type="Character">
+<Text>WILL</Text>
+</Paragraph>
+<Paragraph Type="Dialogue">
+<Text>Dad?</Text>
+</Paragraph>
+</DualDialogue>
+</Paragraph>
+<Paragraph Type="Scene Heading" Number="8" StartsNewPage="Yes">
+<Text>EXT. RIVER - NIGHT</Text>
+</Paragraph>
+<Paragraph Type="General">
+<Text Style="Italic">Daisy, Daisy</Text>
+</Paragraph>
+<Paragraph Type="Action" Alignment="Center">
+<Text>THE END</Text>
+</Paragraph>
+</Content>
+<TitlePage>
+<Content>
+<Paragraph Alignment="Center">
+<Text>Big Fish</Text>
+</Paragraph>
+<Paragraph Alignment="Center">
+<Text></Text>
+</Paragraph>
+<Paragraph Alignment="Center">
+<Text>John August</Text>
+</Paragraph>
+<Paragraph Alignment="Center">
+<Text></Text>
+</Paragraph>
+<Paragraph Alignment="Left">
+<Text>jane@example.com</Text>
+</Paragraph>
+</Content>
+</TitlePage>
+</FinalDraft>
+"#
+    );
+}
+
+#[test]
+fn fdx_scene_numbers() {
+    let doc = Document::from(
+        "INT. HOUSE #3#
+
+INT. BARN
+
+INT. FIELD
+
+INT. RIVER #5#
+
+INT. ROAD
+
+EXT. TOWN
+
+EXT. DOCK #1#",
+    );
+    let imported = Document::from_fdx(&doc.to_fdx()).unwrap();
+    let numbers: Vec<_> = imported.blocks().filter_map(|b| b.scene_number()).collect();
+    assert_eq!(numbers, ["3", "4", "6", "5", "7", "8", "1"]);
+    assert_eq!(numbers.iter().collect::<BTreeSet<_>>().len(), numbers.len());
+
+    let doc = Document::from("INT. HOUSE\n\nINT. BARN #1#");
+    let imported = Document::from_fdx(&doc.to_fdx()).unwrap();
+    let numbers: Vec<_> = imported.blocks().filter_map(|b| b.scene_number()).collect();
+    assert_eq!(numbers, ["2", "1"]);
+}
+
+#[test]
+fn fdx_import() {
+    let doc = Document::from(
+        "Title: Big Fish
+Author: John August
+
+INT. RIVER - DAY #7A#
+
+Edward's **_first_** catch.
+
+EDWARD
+(to himself)
+Got you.
+
+WILL ^
+Dad?
+
+===
+
+EXT. RIVER - NIGHT
+
+> THE END <",
+    );
+    let imported = Document::from_fdx(&doc.to_fdx()).unwrap();
+    let lines = |doc: &Document| -> Vec<(ElmType, String)> {
+        doc.blocks()
+            .map(|e| {
+                let runs: Vec<String> = e
+                    .runs()
+                    .iter()
+                    .map(|r| format!("{}{:?}", r.text(), r.style()))
+                    .collect();
+                (e.elm_type(), runs.concat())
+            })
+            .collect()
+    };
+    assert_eq!(lines(&imported), lines(&doc));
+    let heading = imported.blocks().find(|e| e.elm_type() == ElmType::Heading);
+    assert_eq!(heading.unwrap().scene_number(), Some("7A"));
+
+    // Final Draft 8 keeps notes in the paragraph they're about:
+    let fdx = r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<FinalDraft DocumentType="Script" Template="No" Version="1">
+  <Content>
+    <Paragraph Type="Scene Heading"><Text>int. lake - day</Text></Paragraph>
+    <Paragraph Type="Action">
+      <ScriptNote ID="1"><Paragraph><Text>Too  long?</Text></Paragraph></ScriptNote>
+      <Text>ED swims * far &amp; </Text><Text Style="Italic">away</Text><Text>.</Text>
+    </Paragraph>
+    <Paragraph Type="Action">
+      <Text>An un</Text><Text Style="Bold">believ</Text><Text>able view.</Text>
+    </Paragraph>
+    <Paragraph Type="Character"><Text>Ed</Text></Paragraph>
+    <Paragraph Type="Dialogue"><Text>(beat) So cold.</Text></Paragraph>
+    <Paragraph Type="Transition"><Text>Later</Text></Paragraph>
+    <Paragraph Type="Scene Heading"><Text>EXT. DOCK #1#</Text></Paragraph>
+  </Content>
+  <ScriptNotes>
+    <ScriptNote><Paragraph><Text>Check the ending.</Text></Paragraph></ScriptNote>
+    <ScriptNote Range="71,75"><Paragraph><Text>Cold?</Text></Paragraph></ScriptNote>
+  </ScriptNotes>
+</FinalDraft>"#;
+    let imported = Document::from_fdx(fdx).unwrap();
+    let types: Vec<_> = imported.blocks().map(|e| e.elm_type()).collect();
+    assert_eq!(
+        types,
+        vec![
+            ElmType::Heading,
+            ElmType::Action,
+            ElmType::Action,
+            ElmType::Character,
+            ElmType::Dialogue,
+            ElmType::Transition,
+            ElmType::Heading,
+            ElmType::Note,
+        ]
+    );
+    // Bold inside a word makes the whole word bold, a note with a range goes
+    // at the end of the paragraph it starts in, and a heading's text that
+    // looks like a scene number isn't one:
+    assert_eq!(
+        imported.text(),
+        ".int. lake - day
+
+ED swims \\* far & *away*. [[Too long?]]
+
+An **unbelievable** view.
+
+@Ed
+(beat) So cold. [[Cold?]]
+
+> Later
+
+EXT. DOCK \\#1#
+
+[[Check the ending.]]
+"
+    );
+    let dock = imported.blocks().nth(6).unwrap();
+    assert_eq!(dock.scene_number(), None);
+    assert_eq!(dock.runs()[0].text(), "EXT. DOCK #1#");
+
+    assert_eq!(
+        Document::from_fdx("<FinalDraft><Content></FinalDraft>").unwrap_err(),
+        ImportError::InvalidXml(21)
+    );
+    assert_eq!(
+        Document::from_fdx("<html/>").unwrap_err(),
+        ImportError::UnexpectedRoot("html".to_string())
+    );
+}
+
+#[test]
+fn deep_xml() {
+    let deep = "<FinalDraft><Content>".to_string() + &"<a>".repeat(200000);
+    assert_eq!(
+        Document::from_fdx(&deep).unwrap_err(),
+        ImportError::TooDeep(21 + 254 * 3)
+    );
+    let deep = "<FinalDraft><Content><Paragraph>".to_string() + &"<Text>".repeat(200000);
+    assert!(matches!(
+        Document::from_fdx(&deep),
+        Err(ImportError::TooDeep(_))
+    ));
+
+    // Elements nested deeper than the parser allows are read and dropped
+    // without recursion too:
+    let mut element = crate::xml::XmlElement::default();
+    element
+        .children
+        .push(crate::xml::XmlNode::Text("Hello".to_string()));
+    for _ in 0..200000 {
+        let mut parent = crate::xml::XmlElement::default();
+        parent.children.push(crate::xml::XmlNode::Element(element));
+        element = parent;
+    }
+    assert_eq!(element.text(), "Hello");
+}
+
+#[test]
+fn osf_round_trip() {
+    let doc = Document::from(
+        "Title: Big Fish
+Credit: Written by
+Author: John August
+Draft date: May 2003
+Contact:
+    123 Main St.
+    Anytown
+
+INT. RIVER - DAY #7A#
+
+Edward's **_first_** catch.
+
+~Daisy, Daisy
+~Give me your answer, do
+
+EDWARD
+(to himself)
+Got you.
+
+WILL ^
+Dad?
+
+===
+
+> THE END <",
+    );
+    let osf = doc.to_osf();
+    assert!(osf.contains(
+        "<para>
+<style basestyle=\"Action\" align=\"center\"/>
+<text>Written by</text>
+</para>"
+    ));
+    assert!(osf.contains(
+        "<para>
+<style basestyle=\"Action\" align=\"left\"/>
+<text>123 Main St.</text>
+</para>"
+    ));
+    assert!(osf.contains("<para number=\"7A\">\n<style basestyle=\"Scene Heading\"/>"));
+    assert!(osf.contains("<text bold=\"1\" underline=\"1\">first</text>"));
+    // A verse of lyrics is one paragraph of italic action:
+    assert!(osf.contains(
+        "<para>
+<style basestyle=\"Action\"/>
+<text italic=\"1\">Daisy, Daisy
+Give me your answer, do</text>
+</para>"
+    ));
+    assert!(osf.contains("<para dual=\"1\">\n<style basestyle=\"Character\"/>"));
+    assert!(osf.contains("<para page_break=\"1\">\n<style basestyle=\"Action\" align=\"center\"/>"));
+
+    // Title page entries come back named after where they are on the page:
+    let imported = Document::from_osf(&osf).unwrap();
+    assert_eq!(
+        imported.text(),
+        "Title: Big Fish
+Credit: Written by
+Author: John August
+Contact:
+    May 2003
+    123 Main St.
+    Anytown
+
+INT. RIVER - DAY #7A#
+
+Edward's _**first**_ catch.
+
+*Daisy, Daisy*
+*Give me your answer, do*
+
+EDWARD
+(to himself)
+Got you.
+
+WILL ^
+Dad?
+
+===
+
+> THE END <
+"
+    );
+
+    assert_eq!(
+        Document::from_osf("<FinalDraft/>").unwrap_err(),
+        ImportError::UnexpectedRoot("FinalDraft".to_string())
+    );
+}
+
+#[cfg(feature = "fadein")]
+#[test]
+fn fadein_round_trip() {
+    let doc = Document::from("INT. RIVER - DAY\n\nEdward's *first* catch.\n");
+    let fadein = doc.to_fadein();
+    assert!(fadein.starts_with(b"PK\x03\x04"));
+    let imported = Document::from_fadein(&fadein).unwrap();
+    assert_eq!(
+        imported.text(),
+        "INT. RIVER - DAY #1#\n\nEdward's *first* catch.\n"
+    );
+
+    let mut damaged = fadein.clone();
+    let middle = damaged.len() / 3;
+    damaged[middle] ^= 0xff;
+    assert!(Document::from_fadein(&damaged).is_err());
+    // Sizes in the central directory that don't match the contents:
+    let end = fadein.len() - 22;
+    let directory = u32::from_le_bytes(fadein[end + 16..end + 20].try_into().unwrap()) as usize;
+    let size = directory + 24;
+    for change in [-1i64, 1] {
+        let mut resized = fadein.clone();
+        let value = u32::from_le_bytes(resized[size..size + 4].try_into().unwrap());
+        let value = (i64::from(value) + change) as u32;
+        resized[size..size + 4].copy_from_slice(&value.to_le_bytes());
+        assert_eq!(
+            Document::from_fadein(&resized).unwrap_err(),
+            ImportError::InvalidZip
+        );
+    }
+    let mut huge = fadein.clone();
+    huge[size..size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(
+        Document::from_fadein(&huge).unwrap_err(),
+        ImportError::TooLarge("document.xml".to_string())
+    );
+    assert_eq!(
+        Document::from_fadein(b"not a zip").unwrap_err(),
+        ImportError::InvalidZip
+    );
+}
+
+#[test]
+fn event_stream() {
+    let doc = Document::from(
+        "INT. LAKE - DAY\n\nWILL\n(calling)\nDad, **wait**!\n\n[[Cut this?]]\n\nNo answer.",
+    );
+    let events: Vec<_> = doc.events().collect();
+
+    let text = |t: &'static str| Event::Text(t.into());
+    let start = |t| Event::Start(Tag::Element(t));
+    let end = |t| Event::End(Tag::Element(t));
+    assert_eq!(
+        events,
+        vec![
+            Event::Start(Tag::Scene),
+            start(ElmType::Heading),
+            text("INT. LAKE - DAY"),
+            end(ElmType::Heading),
+            Event::Start(Tag::DialogueBlock),
+            start(ElmType::Character),
+            text("WILL"),
+            end(ElmType::Character),
+            start(ElmType::Parenthetical),
+            text("(calling)"),
+            end(ElmType::Parenthetical),
+            start(ElmType::Dialogue),
+            text("Dad, "),
+            start(ElmType::Bold),
+            text("wait"),
+            end(ElmType::Bold),
+            text("!"),
+            end(ElmType::Dialogue),
+            Event::End(Tag::DialogueBlock),
+            start(ElmType::Note),
+            text("Cut this?"),
+            end(ElmType::Note),
+            start(ElmType::Action),
+            text("No answer."),
+            end(ElmType::Action),
+            Event::End(Tag::Scene),
+        ]
+    );
+
+    // Filters are plain iterator adapters between parser and renderer:
+    let mut in_note = 0;
+    let mut in_character = false;
+    let rendered: String = doc
+        .events()
+        .filter(|event| {
+            match event {
+                Event::Start(Tag::Element(ElmType::Note)) => in_note += 1,
+                Event::End(Tag::Element(ElmType::Note)) => in_note -= 1,
+                _ => return in_note == 0,
+            }
+            false
+        })
+        .filter_map(|event| match event {
+            Event::Start(Tag::Element(ElmType::Character)) => {
+                in_character = true;
+                None
+            }
+            Event::Text(text) if in_character => {
+                in_character = false;
+                Some(text.replace("WILL", "WILLIAM"))
+            }
+            Event::Text(text) => Some(text.into_owned()),
+            Event::End(Tag::Element(elm_type)) if elm_type.is_block() => Some("\n".to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        rendered,
+        "INT. LAKE - DAY\nWILLIAM\n(calling)\nDad, wait!\nNo answer.\n"
+    );
+}
+
+#[test]
+fn diagnostics_for_unclosed_markup() {
+    let text = "He sighs. *So **tired.\n\n(beat)\n\nA [[note\n\nBOB\n(quietly)\nDone. /* the end";
+    let mut doc = Document::from(text);
+
+    let found: Vec<_> = doc
+        .diagnostics()
+        .iter()
+        .map(|d| (d.severity(), d.range(), &text[d.range()]))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Severity::Warning, 10..11, "*"),
+            (Severity::Warning, 14..16, "**"),
+            (Severity::Warning, 24..30, "(beat)"),
+            (Severity::Warning, 34..36, "[["),
+            (Severity::Error, 62..64, "/*"),
+        ]
+    );
+    assert_eq!(
+        doc.diagnostics()[1].to_string(),
+        "warning at 14..16: bold text is never closed"
+    );
+
+    // Closing the bold in place keeps the diagnostics after it in sync:
+    doc.edit(22..22, "**").unwrap();
+    let ranges: Vec<_> = doc.diagnostics().iter().map(|d| d.range()).collect();
+    assert_eq!(ranges, vec![10..11, 26..32, 36..38, 64..66]);
+}
+
+// A small xorshift generator, so the property tests below are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+// Random text made of the pieces of Fountain syntax most likely to trip up
+// the parser:
+fn random_fountain(rng: &mut Rng) -> String {
+    const PIECES: &[&str] = &[
+        "*",
+        "**",
+        "***",
+        "_",
+        "\\",
+        "/*",
+        "*/",
+        "[[",
+        "]]",
+        "\n",
+        "\n\n",
+        " ",
+        "  ",
+        "INT. HOUSE",
+        "BOB",
+        "Bob",
+        "(",
+        ")",
+        "(beat)",
+        "é",
+        "\u{1F41F}",
+        "\u{FFFD}",
+        "a",
+        "!",
+        "@",
+        ".",
+        ">",
+        "<",
+        "~",
+        "#",
+        "=",
+        "===",
+        "TO:",
+        "^",
+        "\t",
+        "\r\n",
+        "Title:",
+        "    ",
+    ];
+    let len = rng.below(60);
+    (0..len).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+}
+
+fn check_document(doc: &Document) {
+    let text = doc.text();
+    for element in doc.elements() {
+        let range = element.offset()..element.offset() + element.len();
+        assert_eq!(text.get(range), Some(element.text()));
+        assert!(element.runs().iter().all(|run| !run.text().is_empty()));
+        assert!(element.spans().all(|span| {
+            span.offset() >= element.offset()
+                && span.offset() + span.len() <= element.offset() + element.len()
+        }));
+    }
+    for diagnostic in doc.diagnostics() {
+        assert!(text.get(diagnostic.range()).is_some());
+    }
+    let mut ids: Vec<_> = doc.elements().map(|e| e.id().unwrap()).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), doc.elements().count());
+    doc.events().for_each(drop);
+    let canonical = Document::from(doc.to_fountain(Format::Canonical));
+    canonical.events().for_each(drop);
+}
+
+// Parsing near the end of 4 GiB, without the memory for a document that
+// long:
+#[cfg(feature = "compact")]
+#[test]
+fn compact_offsets_stop_at_4_gib() {
+    let offset = u32::MAX as usize - 10;
+    let (markup, diagnostics) = parsing::parse_str("A\nB\n\nCAN'T FIT\n", offset);
+    let starts: Vec<_> = markup.iter().map(|e| e.start() - offset).collect();
+    assert_eq!(starts, vec![0, 2]);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity(), Severity::Error);
+    assert_eq!(diagnostics[0].range(), offset + 5..offset + 15);
+}
+
+#[test]
+fn random_text_never_panics() {
+    let mut rng = Rng(0x5EED_F0CA_CC1A);
+    for _ in 0..3000 {
+        let text = random_fountain(&mut rng);
+        let doc = Document::from(text.as_str());
+        check_document(&doc);
+
+        // Parsing in chunks matches parsing all at once:
+        #[cfg(feature = "std")]
+        {
+            let starts = parsing::chunk_starts(&text, 1 + rng.below(4), 1);
+            assert_eq!(
+                parsing::parse_chunked(&text, &starts),
+                parsing::parse_str(&text, 0),
+                "parsing {:?} in chunks at {:?}",
+                text,
+                starts
+            );
+        }
+
+        // Random slicing of the element list:
+        let (a, b) = (rng.below(50), rng.below(50));
+        Elements::new(&doc, a, b).for_each(drop);
+
+        // Editing any valid range matches parsing the edited text:
+        let mut edited = doc.clone();
+        let (mut start, mut end) = (rng.below(text.len() + 1), rng.below(text.len() + 1));
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (start, end) = (start.min(end), start.max(end));
+        let replacement = random_fountain(&mut rng);
+        edited.edit(start..end, &replacement).unwrap();
+        let mut expected = text.clone();
+        expected.replace_range(start..end, &replacement);
+        let expected = Document::from(expected);
+        assert_eq!(
+            edited.elements().collect::<Vec<_>>(),
+            expected.elements().collect::<Vec<_>>(),
+            "editing {:?} at {}..{} with {:?}",
+            text,
+            start,
+            end,
+            replacement
+        );
+        assert_eq!(edited.diagnostics(), expected.diagnostics());
+        check_document(&edited);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn random_bytes_never_panic() {
+    let mut rng = Rng(0xB17E_5EED);
+    for _ in 0..3000 {
+        let len = rng.below(80);
+        let bytes: Vec<u8> = (0..len)
+            .map(|_| match rng.below(4) {
+                0 => rng.next() as u8,
+                1 => b"\n*_/[]\\(@"[rng.below(9)],
+                _ => b'A' + rng.below(26) as u8,
+            })
+            .collect();
+
+        let mut parser = Parser::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rng.below(rest.len()) + 1);
+            parser.feed(chunk).for_each(drop);
+            rest = tail;
+        }
+        parser.finish().for_each(drop);
+        let doc = parser.into_document();
+        check_document(&doc);
+
+        let expected = Document::from(String::from_utf8_lossy(&bytes).into_owned());
+        assert_eq!(doc.text(), expected.text());
+        assert_eq!(
+            doc.elements().collect::<Vec<_>>(),
+            expected.elements().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::layout::{Layout, Line};
+use crate::paging::{paginate, title_page, Page, PageGeometry};
+
+use alloc::format;
+use alloc::string::String;
+
+/// Writes the document laid out for `geometry` as fixed-width text, with
+/// pages separated by a form feed and numbered from the second one on, like
+/// `write_pdf()`.
+///
+/// Lines wrap where they do on paper: action at `geometry.columns`, 60 on US
+/// Letter, and dialogue at 35. The 61 columns sometimes quoted for action
+/// would put the last character in the 1" right margin. Scene headings are
+/// uppercased by the layout, in the PDF too.
+pub fn write_text(layout: &Layout, geometry: PageGeometry) -> String {
+    let mut text = String::new();
+    if let Some(page) = title_page(layout, geometry) {
+        write_page(&mut text, &page);
+    }
+    for (i, page) in paginate(&layout.blocks, geometry)
+        .pages()
+        .iter()
+        .enumerate()
+    {
+        if !text.is_empty() {
+            text.push('\u{c}');
+            text.push('\n');
+        }
+        if i > 0 {
+            let number = format!("{}.", i + 1);
+            text.push_str(&format!("{:>width$}\n\n", number, width = geometry.columns));
+        }
+        write_page(&mut text, page);
+    }
+
+    text
+}
+
+// Blank lines at the end of a page are left out:
+fn write_page(text: &mut String, page: &Page) {
+    let len = page.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    for line in &page[..len] {
+        if let Some(line) = line {
+            write_line(text, line);
+        }
+        text.push('\n');
+    }
+}
+
+fn write_line(text: &mut String, line: &Line) {
+    for _ in 0..line.indent {
+        text.push(' ');
+    }
+    for (run, _) in &line.runs {
+        text.push_str(run);
+    }
+}
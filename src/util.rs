@@ -1,3 +1,7 @@
 pub fn is_ascii_char(byte: u8) -> bool {
     byte & 0b10000000 == 0
 }
+
+pub fn is_continuation_byte(byte: u8) -> bool {
+    byte & 0b11000000 == 0b10000000
+}
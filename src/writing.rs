@@ -0,0 +1,165 @@
+use crate::markup::{Element, ElmType, Spans};
+use crate::parsing::{classify_line, classify_title_line};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// How `Screenplay::to_fountain()` writes a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Reproduces the text the document was parsed from, byte for byte.
+    Lossless,
+    /// Rewrites the document with a single blank line between paragraphs,
+    /// uppercase character cues, and forced markers only on the lines that
+    /// would otherwise parse as something else.
+    Canonical,
+}
+
+/// Writes the document with the given `text` and `blocks`.
+pub fn write_fountain(text: &str, blocks: Spans, format: Format) -> String {
+    match format {
+        Format::Lossless => text.to_string(),
+        Format::Canonical => write_paragraphs(&paragraphs(text, blocks)),
+    }
+}
+
+// Groups the block elements of the document by the blank lines between them:
+fn paragraphs(text: &str, blocks: Spans) -> Vec<Vec<(ElmType, String)>> {
+    let mut paragraphs: Vec<Vec<(ElmType, String)>> = Vec::new();
+    let mut prev_end = None;
+    for element in blocks.filter(|e| e.elm_type().is_block()) {
+        let blank_before = match prev_end {
+            Some(end) => text
+                .get(end..element.offset())
+                .is_none_or(|gap| gap.matches('\n').count() > 1),
+            None => true,
+        };
+        prev_end = Some(element.offset() + element.len());
+
+        let line = (element.elm_type(), canonical_line(&element));
+        match paragraphs.last_mut() {
+            Some(paragraph) if !blank_before => paragraph.push(line),
+            _ => paragraphs.push(vec![line]),
+        }
+    }
+
+    paragraphs
+}
+
+// The text of an element without the markers forcing its type, which
+// `write_paragraphs()` puts back where they're needed:
+fn canonical_line(element: &Element) -> String {
+    let text = element.text();
+    let unforced = |marker| text.strip_prefix(marker).unwrap_or(text).trim_start();
+    match element.elm_type() {
+        ElmType::Action => unforced('!').to_string(),
+        ElmType::Character => unforced('@').to_uppercase(),
+        ElmType::Heading => unforced('.').to_string(),
+        ElmType::Transition => unforced('>').to_string(),
+        ElmType::Section => {
+            let title = text.trim_start_matches('#');
+            let depth = text.len() - title.len();
+            format!("{} {}", "#".repeat(depth), title.trim_start())
+        }
+        ElmType::Synopsis => format!("= {}", unforced('=')),
+        ElmType::Centered => {
+            let centered = text.trim_start_matches('>').trim_end_matches('<');
+            format!("> {} <", centered.trim())
+        }
+        ElmType::PageBreak => "===".to_string(),
+        ElmType::TitleValue => format!("    {}", text),
+        _ => text.to_string(),
+    }
+}
+
+/// Writes paragraphs of typed lines as Fountain, separated by blank lines.
+/// Lines that wouldn't parse as their type get the marker forcing it.
+pub fn write_paragraphs<S>(paragraphs: &[Vec<(ElmType, S)>]) -> String
+where
+    S: AsRef<str>,
+{
+    let mut text = String::new();
+    for paragraph in paragraphs.iter().filter(|p| !p.is_empty()) {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        let mut prev_type = None;
+        for (i, (elm_type, line)) in paragraph.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            force_line(
+                &mut text,
+                *elm_type,
+                line.as_ref(),
+                prev_type,
+                i + 1 == paragraph.len(),
+            );
+            prev_type = Some(*elm_type);
+        }
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+
+    text
+}
+
+fn force_line(
+    text: &mut String,
+    elm_type: ElmType,
+    line: &str,
+    prev_type: Option<ElmType>,
+    next_blank: bool,
+) {
+    let all_uppercase = !line.bytes().any(|b| b.is_ascii_lowercase());
+    let indented = line.starts_with([' ', '\t']);
+    let title_type = classify_title_line(line.trim_start(), indented, text.is_empty(), prev_type);
+    let parsed_type = title_type.unwrap_or_else(|| {
+        classify_line(
+            line,
+            all_uppercase,
+            prev_type.is_none(),
+            prev_type,
+            next_blank,
+        )
+    });
+    if parsed_type != elm_type {
+        match elm_type {
+            ElmType::Action => text.push('!'),
+            ElmType::Character => text.push('@'),
+            ElmType::Heading => text.push('.'),
+            ElmType::Transition => text.push_str("> "),
+            ElmType::Dialogue | ElmType::Parenthetical => text.push('\\'),
+            _ => {}
+        }
+    }
+    // A forced transition ending in '<' would be centered text:
+    match line.strip_suffix('<') {
+        Some(rest) if elm_type == ElmType::Transition && !rest.ends_with('\\') => {
+            text.push_str(rest);
+            text.push_str("\\<");
+        }
+        _ => text.push_str(line),
+    }
+}
+
+/// Escapes the characters in `text` that Fountain would read as emphasis,
+/// boneyard or notes.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut prev = '\0';
+    for ch in text.chars() {
+        match ch {
+            '*' | '_' | '\\' => escaped.push('\\'),
+            '[' | ']' if prev == ch => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(ch);
+        prev = ch;
+    }
+
+    escaped
+}
@@ -0,0 +1,262 @@
+use crate::importing::ImportError;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::take;
+
+// How deep elements nest in the files that are read, far more than the
+// screenplay formats ever need, to keep files that nest without end from
+// using up the memory:
+const MAX_DEPTH: usize = 256;
+
+/// An element of a parsed XML file, with its attributes and what's inside.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+}
+
+impl XmlElement {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterates over the elements directly inside this one.
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|child| match child {
+            XmlNode::Element(element) => Some(element),
+            XmlNode::Text(_) => None,
+        })
+    }
+
+    /// The first element named `name` directly inside this one.
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// All the text inside this element, including that of the elements in
+    /// it.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        // The children left to visit at each level, rather than recursion
+        // deep enough to overflow the stack:
+        let mut levels = vec![self.children.iter()];
+        while let Some(children) = levels.last_mut() {
+            match children.next() {
+                Some(XmlNode::Element(element)) => levels.push(element.children.iter()),
+                Some(XmlNode::Text(t)) => text.push_str(t),
+                None => {
+                    levels.pop();
+                }
+            }
+        }
+
+        text
+    }
+}
+
+// The elements inside are dropped one after the other, rather than each
+// dropping those inside it, which could overflow the stack:
+impl Drop for XmlElement {
+    fn drop(&mut self) {
+        let mut children = take(&mut self.children);
+        while let Some(child) = children.pop() {
+            if let XmlNode::Element(mut element) = child {
+                children.append(&mut element.children);
+            }
+        }
+    }
+}
+
+/// Parses the root element of `xml`, skipping the declaration, comments,
+/// processing instructions and doctype around it.
+///
+/// It's just enough XML for the screenplay formats that use it: there's no
+/// validation, namespaces or entities other than the predefined ones.
+pub fn parse_xml(xml: &str) -> Result<XmlElement, ImportError> {
+    let invalid = ImportError::InvalidXml;
+    let start = if xml.starts_with('\u{feff}') { 3 } else { 0 };
+    let mut pos = start;
+    // The bottom of the stack holds the root element once it's closed:
+    let mut stack = vec![XmlElement::default()];
+    while pos < xml.len() {
+        let Some(lt) = xml[pos..].find('<').map(|i| pos + i) else {
+            if !xml[pos..].trim().is_empty() {
+                return Err(invalid(pos));
+            }
+            break;
+        };
+        if lt > pos {
+            let text = &xml[pos..lt];
+            if stack.len() > 1 {
+                let text = unescape(text).ok_or(invalid(pos))?;
+                push_text(&mut stack, text);
+            } else if !text.trim().is_empty() {
+                return Err(invalid(pos));
+            }
+        }
+
+        let rest = &xml[lt..];
+        let skip_to = |end: &str| {
+            let end = rest.find(end).map(|i| lt + i + end.len());
+            end.ok_or(invalid(lt))
+        };
+        if rest.starts_with("<?") {
+            pos = skip_to("?>")?;
+        } else if rest.starts_with("<!--") {
+            pos = skip_to("-->")?;
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let len = cdata.find("]]>").ok_or(invalid(lt))?;
+            push_text(&mut stack, cdata[..len].to_string());
+            pos = lt + 9 + len + 3;
+        } else if rest.starts_with("<!") {
+            pos = skip_to(">")?;
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let len = tag.find('>').ok_or(invalid(lt))?;
+            let element = stack
+                .pop()
+                .filter(|_| !stack.is_empty())
+                .ok_or(invalid(lt))?;
+            if tag[..len].trim() != element.name {
+                return Err(invalid(lt));
+            }
+            stack
+                .last_mut()
+                .ok_or(invalid(lt))?
+                .children
+                .push(XmlNode::Element(element));
+            pos = lt + 2 + len + 1;
+        } else {
+            let (element, empty, len) = start_tag(rest).ok_or(invalid(lt))?;
+            if stack.len() == 1 && !stack[0].children.is_empty() {
+                return Err(invalid(lt));
+            }
+            if stack.len() > MAX_DEPTH {
+                return Err(ImportError::TooDeep(lt));
+            }
+            match stack.last_mut() {
+                Some(parent) if empty => parent.children.push(XmlNode::Element(element)),
+                _ => stack.push(element),
+            }
+            pos = lt + len;
+        }
+    }
+
+    match stack.pop() {
+        Some(mut document) if stack.is_empty() && document.children.len() == 1 => {
+            match document.children.pop() {
+                Some(XmlNode::Element(root)) => Ok(root),
+                _ => Err(invalid(xml.len())),
+            }
+        }
+        _ => Err(invalid(xml.len())),
+    }
+}
+
+// Text next to text, like around a CDATA section, goes in the same node:
+fn push_text(stack: &mut [XmlElement], text: String) {
+    let Some(parent) = stack.last_mut() else {
+        return;
+    };
+    match parent.children.last_mut() {
+        Some(XmlNode::Text(prev)) => prev.push_str(&text),
+        _ => parent.children.push(XmlNode::Text(text)),
+    }
+}
+
+// Parses the start tag at the beginning of `tag`, returning the element, if
+// it's empty like "<br/>", and the length of the tag:
+fn start_tag(tag: &str) -> Option<(XmlElement, bool, usize)> {
+    let is_name_end = |ch: char| ch.is_whitespace() || matches!(ch, '/' | '>' | '=');
+    let mut rest = &tag[1..];
+    let name_len = rest.find(is_name_end)?;
+    if name_len == 0 {
+        return None;
+    }
+    let mut element = XmlElement {
+        name: rest[..name_len].to_string(),
+        attributes: Vec::new(),
+        children: Vec::new(),
+    };
+    rest = &rest[name_len..];
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return Some((element, true, tag.len() - after.len()));
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return Some((element, false, tag.len() - after.len()));
+        }
+
+        let key_len = rest.find(is_name_end)?;
+        if key_len == 0 {
+            return None;
+        }
+        let key = rest[..key_len].to_string();
+        rest = rest[key_len..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = rest.chars().next().filter(|&q| q == '"' || q == '\'')?;
+        let value_len = rest[1..].find(quote)?;
+        let value = unescape(&rest[1..1 + value_len])?;
+        element.attributes.push((key, value));
+        rest = &rest[1 + value_len + 1..];
+    }
+}
+
+// Replaces the predefined entities and character references in `text`:
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        let semicolon = rest[amp..].find(';')? + amp;
+        let entity = &rest[amp + 1..semicolon];
+        let ch = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => entity.strip_prefix('#')?.parse().ok()?,
+                };
+                char::from_u32(code)?
+            }
+        };
+        unescaped.push(ch);
+        rest = &rest[semicolon + 1..];
+    }
+    unescaped.push_str(rest);
+
+    Some(unescaped)
+}
+
+/// Appends `text` to `xml`, escaped for use in text or attribute values, and
+/// leaving out the control characters XML doesn't allow.
+pub fn push_escaped(xml: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => xml.push_str("&amp;"),
+            '<' => xml.push_str("&lt;"),
+            '>' => xml.push_str("&gt;"),
+            '"' => xml.push_str("&quot;"),
+            '\'' => xml.push_str("&apos;"),
+            '\t' | '\n' | '\r' => xml.push(ch),
+            _ if ch.is_control() => {}
+            _ => xml.push(ch),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::importing::ImportError;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// The largest file that's read, many times the size of the longest
+// screenplays:
+const MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/// Writes a zip archive of the given files, deflated. Modification times
+/// are all the start of 1980, the earliest zip allows.
+///
+/// Panics past the limits of zip without its 64-bit extension: 4 GiB for
+/// the archive and its files, 65535 files, and names of 65535 bytes.
+pub fn write_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let mut directory = Vec::new();
+    for &(name, data) in files {
+        let offset = zip.len();
+        let compressed = compress_to_vec(data, 6);
+        let header = FileHeader {
+            method: DEFLATED,
+            crc: crc32(data),
+            compressed_size: compressed.len(),
+            size: data.len(),
+            name,
+        };
+
+        push_u32(&mut zip, LOCAL_HEADER);
+        header.push(&mut zip);
+        push_u16(&mut zip, 0); // extra field length
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(&compressed);
+
+        push_u32(&mut directory, CENTRAL_HEADER);
+        push_u16(&mut directory, 20); // made by version 2.0
+        header.push(&mut directory);
+        push_u16(&mut directory, 0); // extra field length
+        push_u16(&mut directory, 0); // comment length
+        push_u16(&mut directory, 0); // disk number
+        push_u16(&mut directory, 0); // internal attributes
+        push_u32(&mut directory, 0); // external attributes
+        push_u32(&mut directory, zip_u32(offset));
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = zip.len();
+    zip.extend_from_slice(&directory);
+    push_u32(&mut zip, END_OF_DIRECTORY);
+    push_u16(&mut zip, 0); // this disk
+    push_u16(&mut zip, 0); // disk with the directory
+    push_u16(&mut zip, zip_u16(files.len()));
+    push_u16(&mut zip, zip_u16(files.len()));
+    push_u32(&mut zip, zip_u32(directory.len()));
+    push_u32(&mut zip, zip_u32(directory_offset));
+    push_u16(&mut zip, 0); // comment length
+
+    zip
+}
+
+/// Reads the file named `name` in a zip archive, stored or deflated.
+pub fn read_zip(zip: &[u8], name: &str) -> Result<Vec<u8>, ImportError> {
+    // The end of central directory record is at the end, before a comment
+    // of up to 64 KiB:
+    let end = (0..=zip.len().saturating_sub(22))
+        .rev()
+        .take(22 + 0xffff)
+        .find(|&i| read_u32(zip, i) == Some(END_OF_DIRECTORY))
+        .ok_or(ImportError::InvalidZip)?;
+    let count = read_u16(zip, end + 10).ok_or(ImportError::InvalidZip)?;
+    let mut pos = read_u32(zip, end + 16).ok_or(ImportError::InvalidZip)? as usize;
+
+    for _ in 0..count {
+        if read_u32(zip, pos) != Some(CENTRAL_HEADER) {
+            return Err(ImportError::InvalidZip);
+        }
+        let field = |at: usize| read_u16(zip, pos + at).ok_or(ImportError::InvalidZip);
+        let name_len = field(28)? as usize;
+        let entry_len = 46 + name_len + field(30)? as usize + field(32)? as usize;
+        let entry_name = zip.get(pos + 46..pos + 46 + name_len);
+        if entry_name != Some(name.as_bytes()) {
+            pos += entry_len;
+            continue;
+        }
+
+        let word = |at: usize| read_u32(zip, pos + at).ok_or(ImportError::InvalidZip);
+        let method = field(10)?;
+        let crc = word(16)?;
+        let compressed_size = word(20)? as usize;
+        let size = word(24)? as usize;
+        let local = word(42)? as usize;
+        if read_u32(zip, local) != Some(LOCAL_HEADER) {
+            return Err(ImportError::InvalidZip);
+        }
+        let local_field = |at: usize| read_u16(zip, local + at).ok_or(ImportError::InvalidZip);
+        let start = local + 30 + local_field(26)? as usize + local_field(28)? as usize;
+        let compressed = start
+            .checked_add(compressed_size)
+            .and_then(|end| zip.get(start..end))
+            .ok_or(ImportError::InvalidZip)?;
+
+        // What's inflated is bounded by the size in the directory, and that
+        // by `MAX_SIZE`, so a small archive can't expand to fill the memory:
+        if size > MAX_SIZE {
+            return Err(ImportError::TooLarge(String::from(name)));
+        }
+        let data = match method {
+            STORED => compressed.to_vec(),
+            DEFLATED => decompress_to_vec_with_limit(compressed, size)
+                .map_err(|_| ImportError::InvalidZip)?,
+            _ => return Err(ImportError::InvalidZip),
+        };
+        if data.len() != size || crc32(&data) != crc {
+            return Err(ImportError::InvalidZip);
+        }
+        return Ok(data);
+    }
+
+    Err(ImportError::MissingFile(String::from(name)))
+}
+
+// The part of a file's header that's the same in the local header and in
+// the central directory, from the version needed to the name length:
+struct FileHeader<'a> {
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    name: &'a str,
+}
+
+impl FileHeader<'_> {
+    fn push(&self, zip: &mut Vec<u8>) {
+        push_u16(zip, 20); // version needed, 2.0 for deflate
+        push_u16(zip, 1 << 11); // the name is UTF-8
+        push_u16(zip, self.method);
+        push_u16(zip, 0); // time
+        push_u16(zip, (1 << 5) | 1); // date, January 1 1980
+        push_u32(zip, self.crc);
+        push_u32(zip, zip_u32(self.compressed_size));
+        push_u32(zip, zip_u32(self.size));
+        push_u16(zip, zip_u16(self.name.len()));
+    }
+}
+
+fn zip_u16(value: usize) -> u16 {
+    u16::try_from(value).expect("too many files or too long a name for a zip archive")
+}
+
+fn zip_u32(value: usize) -> u32 {
+    u32::try_from(value).expect("zip archive over 4 GiB")
+}
+
+fn push_u16(zip: &mut Vec<u8>, value: u16) {
+    zip.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(zip: &mut Vec<u8>, value: u32) {
+    zip.extend_from_slice(&value.to_le_bytes());
+}
+
+fn read_u16(zip: &[u8], pos: usize) -> Option<u16> {
+    let bytes = zip.get(pos..pos.checked_add(2)?)?;
+
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(zip: &[u8], pos: usize) -> Option<u32> {
+    let bytes = zip.get(pos..pos.checked_add(4)?)?;
+
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// The CRC-32 zip files check their contents with, bit by bit since the
+// files are small:
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}